@@ -0,0 +1,226 @@
+use std::{path::{Path, PathBuf}, pin::Pin};
+
+use csv::{format::Format, reader::Reader, writer::Writer};
+use futures::StreamExt;
+use models::{error::{Error, ErrorKind}, outcome::OutcomeStatus, transactions::Transaction};
+use serde::Serialize;
+
+pub type TransactionStream<'a> = Pin<Box<dyn futures::Stream<Item = Result<Transaction, anyhow::Error>> + Send + 'a>>;
+
+// InputOrder is the order transactions of several inputs are processed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InputOrder {
+    // One input after the other, in name order.
+    #[default]
+    Name,
+    // Merged by timestamp column, each input is expected to be sorted by it.
+    // Transactions without timestamp go first, ties keep name order.
+    Timestamp,
+}
+
+impl InputOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(InputOrder::Name),
+            "timestamp" => Some(InputOrder::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+// Input is a named source of transactions.
+pub struct Input {
+    pub name: String,
+    pub format: Format,
+    pub reader: Box<Reader>,
+}
+
+impl Input {
+    pub fn new(name: &str, format: Format, reader: Box<Reader>) -> Self {
+        Self { name: name.to_string(), format, reader }
+    }
+}
+
+// resolve_paths expands directories to the input files they contain and glob patterns
+// to the files they match. A path which exists is never read as a pattern, so file names
+// may contain pattern characters. The result is sorted by name without duplicates.
+pub fn resolve_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut resolved = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() && is_input_file(&entry.path()) {
+                    resolved.push(entry.path());
+                } else {
+                    tracing::debug!("Skipping {}, it is not an input file", entry.path().display());
+                }
+            }
+        } else if !path.exists() && path.to_string_lossy().contains(['*', '?', '[']) {
+            let pattern = path.to_string_lossy();
+            let matches = glob::glob(&pattern)
+                .map_err(|e| input_error(format!("invalid pattern {}: {}", pattern, e)))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                return Err(input_error(format!("no input file matches {}", pattern)));
+            }
+            resolved.extend(matches);
+        } else {
+            resolved.push(path.clone());
+        }
+    }
+    resolved.sort();
+    resolved.dedup();
+    Ok(resolved)
+}
+
+// is_input_file tells whether the extension of a file in an input directory is
+// one of a readable format, compressed or not.
+fn is_input_file(path: &Path) -> bool {
+    Format::from_path(path).is_some_and(|format| !format.is_columnar())
+}
+
+// MergedInputs reads the transactions of several inputs in order, each
+// transaction comes with the index of its input.
+pub struct MergedInputs<'a> {
+    order: InputOrder,
+    streams: Vec<Option<TransactionStream<'a>>>,
+    heads: Vec<Option<Result<Transaction, anyhow::Error>>>,
+    current: usize,
+}
+
+impl<'a> MergedInputs<'a> {
+    pub fn new(streams: Vec<TransactionStream<'a>>, order: InputOrder) -> Self {
+        let heads = streams.iter().map(|_| None).collect();
+        Self { order, streams: streams.into_iter().map(Some).collect(), heads, current: 0 }
+    }
+
+    pub async fn next(&mut self) -> Option<(usize, Result<Transaction, anyhow::Error>)> {
+        match self.order {
+            InputOrder::Name => {
+                while let Some(stream) = self.streams.get_mut(self.current) {
+                    match stream.as_mut()?.next().await {
+                        Some(transaction) => return Some((self.current, transaction)),
+                        None => self.current += 1,
+                    }
+                }
+                None
+            },
+            InputOrder::Timestamp => {
+                for (head, stream) in self.heads.iter_mut().zip(self.streams.iter_mut()) {
+                    if let (None, Some(rdr)) = (&head, &mut *stream) {
+                        match rdr.next().await {
+                            Some(transaction) => *head = Some(transaction),
+                            None => *stream = None,
+                        }
+                    }
+                }
+                // Errors and transactions without timestamp sort first, as None < Some.
+                let next = self.heads.iter()
+                    .enumerate()
+                    .filter_map(|(input, head)| head.as_ref().map(|head| (head.as_ref().ok().and_then(|t| t.timestamp), input)))
+                    .min()
+                    .map(|(_, input)| input)?;
+                self.heads[next].take().map(|transaction| (next, transaction))
+            },
+        }
+    }
+}
+
+// FileSummary counts the rows and outcomes of a single input.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileSummary {
+    pub file: String,
+    pub rows: u64,
+    pub invalid: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    pub held: u64,
+    pub failed: u64,
+}
+
+impl FileSummary {
+    pub fn new(file: &str) -> Self {
+        Self { file: file.to_string(), ..Self::default() }
+    }
+
+    // record counts the outcome of a row of the file. System outcomes such as
+    // dispute expiries carry the sequence of the row which triggered them, they are left out.
+    pub fn record(&mut self, status: &OutcomeStatus) {
+        match status {
+            OutcomeStatus::Applied => self.applied += 1,
+            OutcomeStatus::Rejected => self.rejected += 1,
+            OutcomeStatus::Held => self.held += 1,
+            OutcomeStatus::Failed => self.failed += 1,
+            OutcomeStatus::AutoResolved | OutcomeStatus::AutoChargedBack => {},
+        }
+    }
+}
+
+pub async fn write_summaries_csv(writer: &mut Writer, summaries: &[FileSummary]) -> Result<(), Error> {
+    let mut writer = csv_async::AsyncSerializer::from_writer(writer);
+    for summary in summaries {
+        writer.serialize(summary).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+fn input_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use csv::reader::read_csv;
+    use models::{ids::TransactionId, logger::create_span};
+
+    use super::{resolve_paths, InputOrder, MergedInputs, TransactionStream};
+
+    #[test]
+    fn test_resolve_paths() {
+        let dir = std::env::temp_dir().join(format!("inputs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["b.csv", "a.csv", "c.ndjson", "d.csv.gz", "notes.txt", "report.parquet", ".DS_Store", "e[1].csv"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        // Only files of readable formats are taken from a directory.
+        let files = resolve_paths(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(files, vec![dir.join("a.csv"), dir.join("b.csv"), dir.join("c.ndjson"), dir.join("d.csv.gz"), dir.join("e[1].csv")]);
+        // An existing file is not read as a pattern, which would not match itself.
+        assert_eq!(resolve_paths(&[dir.join("e[1].csv")]).unwrap(), vec![dir.join("e[1].csv")]);
+        let files = resolve_paths(&[dir.join("c.ndjson"), dir.join("[ab].csv")]).unwrap();
+        assert_eq!(files, vec![dir.join("a.csv"), dir.join("b.csv"), dir.join("c.ndjson")]);
+        assert!(resolve_paths(&[dir.join("*.tsv")]).is_err());
+        assert_eq!(resolve_paths(&[PathBuf::from("missing.csv")]).unwrap(), vec![PathBuf::from("missing.csv")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_merged_inputs() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for (order, expected) in [
+                (InputOrder::Name, vec![(0, 1), (0, 3), (1, 2), (1, 4), (1, 5)]),
+                (InputOrder::Timestamp, vec![(1, 2), (0, 1), (1, 4), (0, 3), (1, 5)]),
+            ] {
+                let mut first = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,10\ndeposit,1,3,1.0,30\n".as_bytes();
+                let mut second = "type,client,tx,amount,timestamp\ndeposit,2,2,1.0,\ndeposit,2,4,1.0,20\ndeposit,2,5,1.0,30\n".as_bytes();
+                let streams: Vec<TransactionStream> = vec![Box::pin(read_csv(&mut first).await), Box::pin(read_csv(&mut second).await)];
+                let mut merged = MergedInputs::new(streams, order);
+
+                let mut order = Vec::new();
+                while let Some((input, transaction)) = merged.next().await {
+                    order.push((input, transaction.unwrap().id));
+                }
+                assert_eq!(order, expected.into_iter().map(|(input, id)| (input, TransactionId(id))).collect::<Vec<_>>());
+            }
+        })
+    }
+}
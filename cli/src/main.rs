@@ -1,14 +1,17 @@
+mod inputs;
+mod options;
 mod process;
 
 use std::{env, path::PathBuf, sync::Arc, str::FromStr};
 use mem_store::mem_store::MemStore;
-use models::{error::Error, logger::{self, create_span}, infra::SpannedRuntime};
-use tokio::fs::File;
-use crate::process::process_transactions;
+use models::{error::Error, logger::{self, create_span}, infra::{CancellationToken, SpannedRuntime}, store::Store};
+use csv::{compression::{compress, Compression}, format::Format, ndjson::write_transactions_ndjson, writer::write_transactions_csv};
+use tokio::{fs::File, io::AsyncWriteExt};
+use crate::{inputs::{resolve_paths, write_summaries_csv, Input}, options::Options, process::process_transactions};
 
 fn main() -> Result<(), Error> {
-    let args: Vec<String> = env::args().collect();
-    let input_path = &args[1];
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = Options::parse(&args)?;
     let log_file_dir = PathBuf::from_str("./log").unwrap();
     let filter = "debug".to_string();
     let logger = logger::Logger::new(log_file_dir, filter);
@@ -16,15 +19,58 @@ fn main() -> Result<(), Error> {
 
     let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
     let rtc = rt.clone();
-    let path = PathBuf::from(input_path);
-    rt.block_on(init(path, rtc))?;
+    rt.block_on(init(options, rtc))?;
     Ok(())
 }
 
-async fn init(path: PathBuf, rt: Arc<SpannedRuntime>) -> Result<(), Error> {
-    let mut file = File::open(path).await?;
+async fn init(options: Options, rt: Arc<SpannedRuntime>) -> Result<(), Error> {
+    let mut inputs = Vec::new();
+    for path in resolve_paths(&options.input_paths)? {
+        let input = if path.as_os_str() == "-" {
+            Input::new("-", options.input_format.unwrap_or_default(), Box::new(tokio::io::stdin()))
+        } else {
+            Input::new(&path.to_string_lossy(), options.input_format_of(&path), Box::new(File::open(&path).await?))
+        };
+        inputs.push(input);
+    }
     let mut writer = tokio::io::stdout();
     let store = MemStore::default();
-    process_transactions(&mut file, store, &mut writer, rt, 2).await?;
+    // Ctrl-C stops reading the input, transactions already read are processed and reported.
+    // A second Ctrl-C exits right away.
+    let cancel = CancellationToken::default();
+    let token = cancel.clone();
+    rt.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tracing::warn!("Cancelling, press Ctrl-C again to exit without draining");
+            token.cancel();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    let summaries = match &options.outcomes_path {
+        Some(path) => {
+            // Outcome and dead letter files are compressed when their extension says so.
+            let mut outcomes = compress(File::create(path).await?, Compression::from_path(path));
+            let summaries = process_transactions(inputs, store.clone(), &mut writer, Some(&mut outcomes), rt, &options, &cancel).await?;
+            outcomes.shutdown().await?;
+            summaries
+        },
+        None => process_transactions(inputs, store.clone(), &mut writer, None, rt, &options, &cancel).await?,
+    };
+    if let Some(path) = &options.summary_path {
+        let mut summary = File::create(path).await?;
+        write_summaries_csv(&mut summary, &summaries).await?;
+    }
+    if let Some(path) = &options.dead_letter_path {
+        let mut dead_letters = compress(File::create(path).await?, Compression::from_path(path));
+        let transactions = store.get_dead_letters().await?;
+        match options.dead_letter_format() {
+            Format::Csv => write_transactions_csv(&mut dead_letters, transactions).await?,
+            Format::Ndjson => write_transactions_ndjson(&mut dead_letters, transactions).await?,
+            format => return Err(Error::from(format!("dead letters can not be written as {:?}", format))),
+        }
+        dead_letters.shutdown().await?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
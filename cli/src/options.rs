@@ -0,0 +1,411 @@
+use std::{ops::RangeInclusive, path::{Path, PathBuf}};
+
+use engine::dispute::{DisputePolicy, ExpiryAction};
+use models::{error::{Error, ErrorKind}, ids::RawClientId};
+use csv::{columns::AccountColumn, dialect::Dialect, format::Format, reader::ReadMode};
+use publish::{backpressure::Backpressure, report::{ReportOrder, ReportQuery}, sharding::ShardingKind};
+
+use crate::inputs::InputOrder;
+
+// Options holds the command line configuration of the cli.
+// Usage: cli <transactions.csv|dir|glob>... [--order name|timestamp] [--summary <path>] [--stream] [--input-format csv|ndjson] [--output-format csv|ndjson|parquet|arrow] [--outcomes <path>] [--dead-letter <path>] [--workers <n>] [--max-workers <n>] [--rules <rules.toml>]
+//            [--dispute-window <secs>] [--dispute-deadline <secs>]
+//            [--on-dispute-expiry resolve|chargeback]
+//            [--sharding modulo|consistent-hash|load-aware]
+//            [--sort input|client|total] [--locked-only] [--non-zero] [--clients <a-b,c>] [--report-columns <a,b,..>] [--extended]
+//            [--dialect <dialect.toml>] [--delimiter <char>] [--quote <char>] [--no-header] [--columns <a,b,..>] [--map <column>=<field>]
+//            [--strict] [--parse-threads <n>] [--serial] [--priority-lanes] [--progress] [--channel-capacity <n>] [--backpressure block|fail-fast|spill] [--spill-dir <dir>]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    // Files, directories or glob patterns, see inputs::resolve_paths, - reads stdin.
+    pub input_paths: Vec<PathBuf>,
+    pub input_order: InputOrder,
+    // Overrides the format taken from each input file extension.
+    pub input_format: Option<Format>,
+    pub output_format: Format,
+    // Write account rows as accounts change instead of a final report.
+    pub stream: bool,
+    // Order and filters of the account report.
+    pub report: ReportQuery,
+    // Columns of the account report in order, all when empty.
+    pub report_columns: Vec<AccountColumn>,
+    // Add the activity statistics of each client to the account report.
+    pub extended_report: bool,
+    pub outcomes_path: Option<PathBuf>,
+    // Per input file row and outcome counts are written there.
+    pub summary_path: Option<PathBuf>,
+    pub rules_path: Option<PathBuf>,
+    // Transactions which crashed an engine worker are written there.
+    pub dead_letter_path: Option<PathBuf>,
+    pub worker_count: u16,
+    // Workers scale between worker_count and max_workers when set.
+    pub max_workers: Option<u16>,
+    pub sharding: ShardingKind,
+    // Csv layout and column mapping, flags given after --dialect adjust the loaded file.
+    pub dialect: Dialect,
+    // Strict validates the header and each record, reporting the line and column of errors.
+    // NDJSON input has no header, its records are validated the same way.
+    pub read_mode: ReadMode,
+    // Parse csv inputs in chunks on that many threads, see csv::parallel.
+    // NDJSON inputs of the same run are read sequentially, it is refused when all inputs are NDJSON.
+    pub parse_threads: Option<usize>,
+    // Process all transactions on a single worker in input order.
+    pub serial: bool,
+    // Let disputes, chargebacks and admin operations jump ahead of queued deposits and withdrawals.
+    // A dispute may then be applied before a withdrawal read earlier, which can change the outcome of both.
+    pub priority_lanes: bool,
+    // Print a progress line on stderr while processing.
+    pub progress: bool,
+    pub channel_capacity: usize,
+    pub backpressure: Backpressure,
+    pub dispute_policy: DisputePolicy,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            input_paths: Vec::new(),
+            input_order: InputOrder::default(),
+            input_format: None,
+            output_format: Format::default(),
+            stream: false,
+            report: ReportQuery::default(),
+            report_columns: Vec::new(),
+            extended_report: false,
+            outcomes_path: None,
+            summary_path: None,
+            rules_path: None,
+            dead_letter_path: None,
+            worker_count: 2,
+            max_workers: None,
+            sharding: ShardingKind::default(),
+            dialect: Dialect::default(),
+            read_mode: ReadMode::default(),
+            parse_threads: None,
+            serial: false,
+            priority_lanes: false,
+            progress: false,
+            channel_capacity: 10,
+            backpressure: Backpressure::default(),
+            dispute_policy: DisputePolicy::default(),
+        }
+    }
+}
+
+impl Options {
+    // parse builds options from the command line arguments, excluding the program name.
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut options = Options::default();
+        let mut spill = false;
+        let mut spill_dir = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--input-format" => options.input_format = Some(parse_format(arg, args.next())?),
+                "--order" => {
+                    let name = value(arg, args.next())?;
+                    options.input_order = InputOrder::from_name(&name)
+                        .ok_or_else(|| config_error(format!("invalid value {} for {}", name, arg)))?;
+                },
+                "--summary" => options.summary_path = Some(PathBuf::from(value(arg, args.next())?)),
+                "--output-format" => options.output_format = parse_format(arg, args.next())?,
+                "--outcomes" => options.outcomes_path = Some(PathBuf::from(value(arg, args.next())?)),
+                "--dead-letter" => options.dead_letter_path = Some(PathBuf::from(value(arg, args.next())?)),
+                "--rules" => options.rules_path = Some(PathBuf::from(value(arg, args.next())?)),
+                "--workers" => options.worker_count = parse_number(arg, args.next())?,
+                "--max-workers" => options.max_workers = Some(parse_number(arg, args.next())?),
+                "--sharding" => {
+                    let name = value(arg, args.next())?;
+                    options.sharding = ShardingKind::from_name(&name)
+                        .ok_or_else(|| config_error(format!("invalid value {} for {}", name, arg)))?;
+                },
+                "--stream" => options.stream = true,
+                "--sort" => {
+                    let name = value(arg, args.next())?;
+                    options.report.order = ReportOrder::from_name(&name)
+                        .ok_or_else(|| config_error(format!("invalid value {} for {}", name, arg)))?;
+                },
+                "--locked-only" => options.report.locked_only = true,
+                "--non-zero" => options.report.non_zero_only = true,
+                "--clients" => options.report.clients = parse_client_ranges(arg, args.next())?,
+                "--extended" => options.extended_report = true,
+                "--report-columns" => {
+                    options.report_columns = value(arg, args.next())?.split(',')
+                        .map(|name| AccountColumn::from_name(name.trim())
+                            .ok_or_else(|| config_error(format!("invalid column {} for {}", name, arg))))
+                        .collect::<Result<_, _>>()?;
+                },
+                "--dialect" => options.dialect = Dialect::load(Path::new(&value(arg, args.next())?))?,
+                "--delimiter" => options.dialect.delimiter = parse_char(arg, args.next())?,
+                "--quote" => options.dialect.quote = parse_char(arg, args.next())?,
+                "--no-header" => options.dialect.has_headers = false,
+                "--columns" => options.dialect.columns = value(arg, args.next())?.split(',').map(|c| c.trim().to_string()).collect(),
+                "--map" => {
+                    let mapping = value(arg, args.next())?;
+                    let (column, field) = mapping.split_once('=')
+                        .ok_or_else(|| config_error(format!("invalid value {} for {}, expected <column>=<field>", mapping, arg)))?;
+                    options.dialect.mapping.insert(column.trim().to_string(), field.trim().to_string());
+                },
+                "--strict" => options.read_mode = ReadMode::Strict,
+                "--parse-threads" => options.parse_threads = Some(parse_number(arg, args.next())?),
+                "--serial" => options.serial = true,
+                "--priority-lanes" => options.priority_lanes = true,
+                "--progress" => options.progress = true,
+                "--channel-capacity" => options.channel_capacity = parse_number(arg, args.next())?,
+                "--backpressure" => {
+                    options.backpressure = match value(arg, args.next())?.as_str() {
+                        "block" => Backpressure::Block,
+                        "fail-fast" => Backpressure::FailFast,
+                        "spill" => {
+                            spill = true;
+                            Backpressure::Block
+                        },
+                        other => return Err(config_error(format!("invalid value {} for {}", other, arg))),
+                    }
+                },
+                "--spill-dir" => spill_dir = Some(PathBuf::from(value(arg, args.next())?)),
+                "--dispute-window" => options.dispute_policy.open_window = Some(parse_number(arg, args.next())?),
+                "--dispute-deadline" => options.dispute_policy.deadline = Some(parse_number(arg, args.next())?),
+                "--on-dispute-expiry" => {
+                    options.dispute_policy.on_expiry = match value(arg, args.next())?.as_str() {
+                        "resolve" => ExpiryAction::Resolve,
+                        "chargeback" => ExpiryAction::ChargeBack,
+                        other => return Err(config_error(format!("invalid value {} for {}", other, arg))),
+                    }
+                },
+                flag if flag.starts_with("--") => return Err(config_error(format!("unknown option {}", flag))),
+                path => options.input_paths.push(PathBuf::from(path)),
+            }
+        }
+
+        if options.input_paths.is_empty() {
+            return Err(config_error("missing input path".to_string()));
+        }
+        options.dialect.validate()?;
+        if options.stream && (options.report != ReportQuery::default() || !options.report_columns.is_empty() || options.extended_report) {
+            return Err(config_error("report order, filters, columns and --extended do not apply to --stream".to_string()));
+        }
+        if options.input_format.is_some_and(|format| format.is_columnar()) {
+            return Err(config_error("--input-format must be csv or ndjson".to_string()));
+        }
+        if options.stream && options.output_format.is_columnar() {
+            return Err(config_error("--stream requires csv or ndjson output".to_string()));
+        }
+        if options.dead_letter_path.is_some() && options.dead_letter_format().is_columnar() {
+            return Err(config_error("dead letters are written as csv or ndjson".to_string()));
+        }
+        if options.extended_report && !options.report_columns.is_empty() {
+            return Err(config_error("--report-columns can not be combined with --extended".to_string()));
+        }
+        if options.worker_count == 0 {
+            return Err(config_error("--workers must be greater than 0".to_string()));
+        }
+        if options.parse_threads == Some(0) {
+            return Err(config_error("--parse-threads must be greater than 0".to_string()));
+        }
+        if options.parse_threads.is_some() && options.input_paths.iter().all(|path| options.input_format_of(path) == Format::Ndjson) {
+            return Err(config_error("--parse-threads only applies to csv inputs".to_string()));
+        }
+        if options.channel_capacity == 0 {
+            return Err(config_error("--channel-capacity must be greater than 0".to_string()));
+        }
+        if spill {
+            options.backpressure = Backpressure::SpillToDisk(spill_dir.unwrap_or_else(std::env::temp_dir));
+        } else if spill_dir.is_some() {
+            return Err(config_error("--spill-dir requires --backpressure spill".to_string()));
+        }
+        if options.serial && options.priority_lanes {
+            return Err(config_error("--priority-lanes reorders transactions, it can not be combined with --serial".to_string()));
+        }
+        if options.max_workers.is_some_and(|max| max < options.worker_count) {
+            return Err(config_error("--max-workers must not be less than --workers".to_string()));
+        }
+        Ok(options)
+    }
+
+    pub fn input_format_of(&self, path: &Path) -> Format {
+        self.input_format.or_else(|| Format::from_path(path)).unwrap_or_default()
+    }
+
+    // outcomes_format is taken from the outcomes file extension, else the output format.
+    pub fn outcomes_format(&self) -> Format {
+        self.outcomes_path.as_deref().and_then(Format::from_path).unwrap_or(self.output_format)
+    }
+
+    // dead_letter_format is taken from the dead letter file extension, else the
+    // format of the first input so they can be replayed.
+    pub fn dead_letter_format(&self) -> Format {
+        self.dead_letter_path.as_deref().and_then(Format::from_path)
+            .unwrap_or_else(|| self.input_paths.first().map_or(Format::default(), |path| self.input_format_of(path)))
+    }
+}
+
+fn value(flag: &str, value: Option<&String>) -> Result<String, Error> {
+    value.cloned().ok_or_else(|| config_error(format!("missing value for {}", flag)))
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, arg: Option<&String>) -> Result<T, Error> {
+    let arg = value(flag, arg)?;
+    arg.parse::<T>().map_err(|_| config_error(format!("invalid value {} for {}", arg, flag)))
+}
+
+// parse_char accepts a single character, or tab for a tab.
+fn parse_char(flag: &str, arg: Option<&String>) -> Result<char, Error> {
+    let arg = value(flag, arg)?;
+    let mut chars = arg.chars();
+    match (arg.as_str(), chars.next(), chars.next()) {
+        ("tab" | "\\t", _, _) => Ok('\t'),
+        (_, Some(c), None) => Ok(c),
+        _ => Err(config_error(format!("invalid value {} for {}, expected a single character", arg, flag))),
+    }
+}
+
+// parse_client_ranges accepts a comma separated list of client ids and inclusive ranges, e.g. 1-100,250.
+fn parse_client_ranges(flag: &str, arg: Option<&String>) -> Result<Vec<RangeInclusive<RawClientId>>, Error> {
+    let arg = value(flag, arg)?;
+    let invalid = || config_error(format!("invalid value {} for {}", arg, flag));
+    arg.split(',')
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let start = start.trim().parse::<RawClientId>().map_err(|_| invalid())?;
+            let end = end.trim().parse::<RawClientId>().map_err(|_| invalid())?;
+            if start > end {
+                return Err(invalid());
+            }
+            Ok(start..=end)
+        })
+        .collect()
+}
+
+fn parse_format(flag: &str, arg: Option<&String>) -> Result<Format, Error> {
+    let name = value(flag, arg)?;
+    Format::from_name(&name).ok_or_else(|| config_error(format!("invalid value {} for {}", name, flag)))
+}
+
+fn config_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use csv::{columns::AccountColumn, dialect::Dialect, format::Format, reader::ReadMode};
+    use engine::dispute::{DisputePolicy, ExpiryAction};
+    use publish::{backpressure::Backpressure, report::{ReportOrder, ReportQuery}, sharding::ShardingKind};
+
+    use crate::inputs::InputOrder;
+    use super::Options;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(&args(&[
+            "transactions.ndjson", "archive/*.csv", "--order", "timestamp", "--summary", "summary.csv", "--stream", "--delimiter", "tab", "--no-header", "--columns", "id, customer,type,amount", "--map", "id=tx", "--map", "customer=client", "--input-format", "csv", "--output-format", "ndjson", "--outcomes", "outcomes.csv", "--rules", "rules.toml", "--dead-letter", "failed.csv", "--workers", "4", "--max-workers", "8", "--sharding", "load-aware", "--strict", "--parse-threads", "4", "--serial", "--progress",
+            "--channel-capacity", "100", "--backpressure", "spill", "--spill-dir", "/tmp/spill",
+            "--dispute-window", "86400", "--dispute-deadline", "3600", "--on-dispute-expiry", "chargeback",
+        ])).unwrap();
+
+        assert_eq!(options, Options {
+            input_paths: vec![PathBuf::from("transactions.ndjson"), PathBuf::from("archive/*.csv")],
+            input_order: InputOrder::Timestamp,
+            input_format: Some(Format::Csv),
+            output_format: Format::Ndjson,
+            stream: true,
+            report: ReportQuery::default(),
+            report_columns: Vec::new(),
+            extended_report: false,
+            outcomes_path: Some(PathBuf::from("outcomes.csv")),
+            summary_path: Some(PathBuf::from("summary.csv")),
+            rules_path: Some(PathBuf::from("rules.toml")),
+            dead_letter_path: Some(PathBuf::from("failed.csv")),
+            worker_count: 4,
+            max_workers: Some(8),
+            sharding: ShardingKind::LoadAware,
+            dialect: Dialect {
+                delimiter: '\t',
+                has_headers: false,
+                columns: vec!["id".to_string(), "customer".to_string(), "type".to_string(), "amount".to_string()],
+                mapping: [("id", "tx"), ("customer", "client")].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+                ..Dialect::default()
+            },
+            read_mode: ReadMode::Strict,
+            parse_threads: Some(4),
+            serial: true,
+            priority_lanes: false,
+            progress: true,
+            channel_capacity: 100,
+            backpressure: Backpressure::SpillToDisk(PathBuf::from("/tmp/spill")),
+            dispute_policy: DisputePolicy::new(Some(86400), Some(3600), ExpiryAction::ChargeBack),
+        });
+    }
+
+    #[test]
+    fn test_parse_invalid_options() {
+        assert!(Options::parse(&args(&[])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--order", "random"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--workers"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--workers", "0"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--dispute-window", "-1"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--on-dispute-expiry", "ignore"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--workers", "4", "--max-workers", "2"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--sharding", "random"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--channel-capacity", "0"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--parse-threads", "0"])).is_err());
+        assert!(Options::parse(&args(&["a.ndjson", "b.jsonl.gz", "--parse-threads", "2"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--input-format", "ndjson", "--parse-threads", "2"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "b.ndjson", "--parse-threads", "2"])).is_ok());
+        assert!(Options::parse(&args(&["a.csv", "--spill-dir", "/tmp"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--serial", "--priority-lanes"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--priority-lanes"])).unwrap().priority_lanes);
+        assert_eq!(Options::parse(&args(&["a.csv", "--backpressure", "fail-fast"])).unwrap().backpressure, Backpressure::FailFast);
+        assert!(Options::parse(&args(&["a.csv", "--unknown"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--input-format", "xml"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--delimiter", ";;"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--map", "id"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--map", "id=transaction"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--dialect", "missing.toml"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--input-format", "parquet"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--stream", "--output-format", "arrow"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--dead-letter", "failed.parquet"])).is_err());
+    }
+
+    #[test]
+    fn test_report_options() {
+        let options = Options::parse(&args(&["a.csv", "--sort", "total", "--locked-only", "--non-zero", "--clients", "1-100, 250", "--report-columns", "client,total"])).unwrap();
+        assert_eq!(options.report, ReportQuery { order: ReportOrder::Total, locked_only: true, non_zero_only: true, clients: vec![1..=100, 250..=250] });
+        assert_eq!(options.report_columns, vec![AccountColumn::Client, AccountColumn::Total]);
+
+        assert!(Options::parse(&args(&["a.csv", "--sort", "balance"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--clients", "10-1"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--clients", "a-b"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--report-columns", "client,balance"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--stream", "--locked-only"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--stream", "--extended"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--extended", "--report-columns", "client"])).is_err());
+        assert!(Options::parse(&args(&["a.csv", "--extended"])).unwrap().extended_report);
+    }
+
+    #[test]
+    fn test_formats() {
+        let options = Options::parse(&args(&["a.jsonl", "b.csv", "--outcomes", "outcomes.txt", "--dead-letter", "failed.csv"])).unwrap();
+        assert_eq!(options.input_format_of(Path::new("a.jsonl")), Format::Ndjson);
+        assert_eq!(options.input_format_of(Path::new("b.csv")), Format::Csv);
+        assert_eq!(options.output_format, Format::Csv);
+        assert_eq!(options.outcomes_format(), Format::Csv);
+        assert_eq!(options.dead_letter_format(), Format::Csv);
+
+        let options = Options::parse(&args(&["a.jsonl", "--input-format", "csv", "--output-format", "ndjson", "--outcomes", "outcomes.txt", "--dead-letter", "failed"])).unwrap();
+        assert_eq!(options.input_format_of(Path::new("a.jsonl")), Format::Csv);
+        assert_eq!(options.outcomes_format(), Format::Ndjson);
+        assert_eq!(options.dead_letter_format(), Format::Csv);
+
+        let options = Options::parse(&args(&["a.csv", "--output-format", "parquet", "--outcomes", "outcomes.arrow"])).unwrap();
+        assert_eq!(options.output_format, Format::Parquet);
+        assert_eq!(options.outcomes_format(), Format::Arrow);
+    }
+}
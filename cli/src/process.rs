@@ -1,36 +1,183 @@
-use models::{error::Error, infra::SpannedRuntime};
-use std::sync::Arc;
+use models::{error::{Error, ErrorKind}, infra::{CancellationToken, SpannedRuntime}};
+use std::{sync::Arc, time::{Duration, Instant}};
 
+use futures::StreamExt;
 use mem_store::mem_store::MemStore;
-use publish::publish::Publisher;
-use tokio_stream::StreamExt;
-use csv::{reader::{Reader, read_csv}, writer::{write_csv, Writer}};
+use engine::rules::RuleSet;
+use publish::{priority::PriorityLanes, publish::Publisher, scaling::ScalingPolicy};
+use csv::{changes::ChangeWriter, columnar::{write_accounts_columnar, write_extended_columnar, write_outcomes_columnar}, columns::{write_csv_columns, write_ndjson_columns, AccountColumn}, format::Format, ndjson::{read_ndjson_with_mode, write_extended_ndjson, write_ndjson, write_outcomes_ndjson}, parallel::{read_csv_parallel, ParallelOptions}, reader::{read_csv_with_dialect, HeaderError}, writer::{write_csv, write_extended_csv, write_outcomes_csv, Writer}};
 
+use crate::{inputs::{FileSummary, Input, MergedInputs, TransactionStream}, options::Options};
 
-pub async fn process_transactions(reader: &mut Reader, store: MemStore, writer: &mut Writer, rt: Arc<SpannedRuntime>, worker_count: u16) -> Result<(), Error> {
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
-    let mut rdr = read_csv(reader).await;
-    let mut publisher = Publisher::new(store, rt, worker_count);
-    while let Some(t) = rdr.next().await {
-        publisher.post_txn(t.unwrap()).await?;
+// process_transactions processes all inputs through one publisher into a single
+// report, and returns the summary of each input in input order.
+// With options.stream account rows are written as accounts change instead of
+// a final report.
+// It stops reading once cancel is cancelled, the transactions already read are
+// still processed and reported.
+pub async fn process_transactions(mut inputs: Vec<Input>, store: MemStore, writer: &mut Writer, outcomes: Option<&mut Writer>, rt: Arc<SpannedRuntime>, options: &Options, cancel: &CancellationToken) -> Result<Vec<FileSummary>, Error> {
+
+    let mut summaries = inputs.iter().map(|input| FileSummary::new(&input.name)).collect::<Vec<_>>();
+    let mut streams = Vec::<TransactionStream>::new();
+    for input in inputs.iter_mut() {
+        streams.push(match input.format {
+            Format::Csv => match options.parse_threads {
+                Some(threads) => Box::pin(read_csv_parallel(&mut input.reader, options.read_mode, options.dialect.clone(), ParallelOptions::new(threads)).await),
+                None => Box::pin(read_csv_with_dialect(&mut input.reader, options.read_mode, options.dialect.clone()).await),
+            },
+            Format::Ndjson => Box::pin(read_ndjson_with_mode(&mut input.reader, options.read_mode).await),
+            format => return Err(Error::new(ErrorKind::ConfigError(format!("{} can not be read as {:?}, it is an output format", input.name, format)))),
+        });
+    }
+    let mut rdr = MergedInputs::new(streams, options.input_order);
+    let mut publisher = Publisher::new(store, rt, options.worker_count)
+        .with_dispute_policy(options.dispute_policy.clone())
+        .with_sharding(options.sharding.strategy())
+        .with_channel_capacity(options.channel_capacity)
+        .with_backpressure(options.backpressure.clone())
+        .with_serial(options.serial);
+    if options.priority_lanes {
+        publisher = publisher.with_priority_lanes(PriorityLanes::default());
+    }
+    if let Some(max_workers) = options.max_workers {
+        publisher = publisher.with_scaling(ScalingPolicy::new(options.worker_count, max_workers));
+    }
+    if let Some(path) = &options.rules_path {
+        publisher = publisher.with_rules(Arc::new(RuleSet::load(path)?));
+    }
+    let (change_feed, mut changes) = tokio::sync::mpsc::unbounded_channel();
+    let mut change_writer = None;
+    if options.stream {
+        publisher = publisher.with_change_feed(change_feed);
+        change_writer = Some(ChangeWriter::new(&mut *writer, options.output_format)?);
+    }
+    let mut rows = 0u64;
+    // Input of each posted transaction, indexed by the sequence the publisher stamps.
+    let mut sources = Vec::new();
+    let mut last_progress = Instant::now();
+    loop {
+        let t = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                tracing::warn!("Processing cancelled after {} rows, draining in-flight transactions", rows);
+                break;
+            },
+            Some(change) = changes.recv(), if options.stream => {
+                change_writer.as_mut().expect("change writer set when streaming").write(change).await?;
+                continue;
+            },
+            t = rdr.next() => match t {
+                Some(t) => t,
+                None => break,
+            },
+        };
+        rows += 1;
+        let (input, t) = t;
+        summaries[input].rows += 1;
+        match t {
+            Ok(transaction) => {
+                sources.push(input);
+                match publisher.post_txn(transaction).await {
+                    // The publisher reports the rejected transaction, carry on with the next ones.
+                    Err(e) if matches!(*e.kind, ErrorKind::QueueFull(_)) => tracing::warn!("Rejected record of {}: {}", summaries[input].file, e),
+                    result => result?,
+                }
+            },
+            // A file with an invalid strict header can not be read at all, stop the run.
+            Err(e) if e.is::<HeaderError>() => {
+                let message = format!("Invalid header of {}: {}", summaries[input].file, e);
+                return Err(Error::new(ErrorKind::IO(std::io::Error::new(std::io::ErrorKind::InvalidData, message))));
+            },
+            Err(e) => {
+                summaries[input].invalid += 1;
+                tracing::error!("Skipping invalid record of {}: {}", summaries[input].file, e);
+            },
+        }
+        if options.progress && last_progress.elapsed() >= PROGRESS_INTERVAL {
+            eprint!("\rread {} rows, {}", rows, publisher.progress());
+            last_progress = Instant::now();
+        }
+    }
+    for metrics in publisher.queue_metrics() {
+        tracing::info!(?metrics, "Engine worker queue");
+    }
+    if let Some(change_writer) = change_writer.as_mut() {
+        // Keep streaming changes while the workers drain their queues.
+        let shutdown = publisher.shutdown_gracefully();
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                Some(change) = changes.recv() => change_writer.write(change).await?,
+            }
+        }
+        while let Ok(change) = changes.try_recv() {
+            change_writer.write(change).await?;
+        }
+    } else {
+        publisher.shutdown_gracefully().await;
+    }
+    if options.progress {
+        eprintln!("\rread {} rows, {}", rows, publisher.progress());
+    }
+    drop(change_writer);
+    if options.extended_report {
+        let report = publisher.get_extended_report_with(&options.report).await?;
+        match options.output_format {
+            Format::Csv => write_extended_csv(writer, report).await?,
+            Format::Ndjson => write_extended_ndjson(writer, report).await?,
+            format => write_extended_columnar(writer, format, report).await?,
+        }
+    } else if !options.stream {
+        let report = publisher.get_report_with(&options.report).await?;
+        match (options.output_format, options.report_columns.is_empty()) {
+            (Format::Csv, true) => write_csv(writer, report).await?,
+            (Format::Ndjson, true) => write_ndjson(writer, report).await?,
+            (Format::Csv, false) => write_csv_columns(writer, report, &options.report_columns).await?,
+            (Format::Ndjson, false) => write_ndjson_columns(writer, report, &options.report_columns).await?,
+            (format, true) => write_accounts_columnar(writer, format, report, &AccountColumn::ALL).await?,
+            (format, false) => write_accounts_columnar(writer, format, report, &options.report_columns).await?,
+        }
+    }
+
+    let all_outcomes = publisher.get_outcomes().await?.collect::<Vec<_>>().await;
+    for outcome in &all_outcomes {
+        if let Some(input) = outcome.sequence.and_then(|sequence| sources.get(sequence as usize)) {
+            summaries[*input].record(&outcome.status);
+        }
     }
-    publisher.shutdown_gracefully().await;
-    let report = publisher.get_report().await?;
-    write_csv(writer, report).await?;
-    Ok(())
+    if let Some(outcomes) = outcomes {
+        let outcome_stream = futures::stream::iter(all_outcomes);
+        match options.outcomes_format() {
+            Format::Csv => write_outcomes_csv(outcomes, outcome_stream).await?,
+            Format::Ndjson => write_outcomes_ndjson(outcomes, outcome_stream).await?,
+            format => write_outcomes_columnar(outcomes, format, outcome_stream).await?,
+        }
+    }
+    for summary in &summaries {
+        tracing::info!(?summary, "Input processed");
+    }
+    Ok(summaries)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{pin::Pin, sync::Arc, task::{Context, Poll}};
 
     use futures_util::stream::FuturesUnordered;
     use futures_util::StreamExt;
 
+    use engine::dispute::{DisputePolicy, ExpiryAction};
     use mem_store::mem_store::MemStore;
-    use models::{logger::create_span, infra::SpannedRuntime};
-    use tokio::io::BufWriter;
+    use models::{logger::create_span, infra::{CancellationToken, SpannedRuntime}};
+    use tokio::io::{AsyncRead, AsyncReadExt, BufWriter, ReadBuf};
+
+    use csv::{columns::AccountColumn, format::Format, reader::ReadMode};
+    use publish::{backpressure::Backpressure, report::{ReportOrder, ReportQuery}};
 
+    use crate::{inputs::{FileSummary, Input}, options::Options};
     use super::process_transactions;
 
     // This tests parallelly starts multiple process_transactions csv.
@@ -48,34 +195,22 @@ mod tests {
         let buffer = output1.into_inner();
         let csv = String::from_utf8_lossy(&buffer);
         
-        let expected = (csv
-            == "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n")
-            || (csv == "client,available,held,total,locked\n2,0.0,0.0,0.0,true\n1,250.0,0.0,250.0,false\n");
-
-        assert!(expected);
+        assert_eq!(csv, "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n");
 
         let buffer = output2.into_inner();
         let csv = String::from_utf8_lossy(&buffer);
         
-        let expected = (csv
-            == "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n")
-            || (csv == "client,available,held,total,locked\n2,0.0,0.0,0.0,true\n1,250.0,0.0,250.0,false\n");
-
-        assert!(expected);
+        assert_eq!(csv, "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n");
 
         let buffer = output3.into_inner();
         let csv = String::from_utf8_lossy(&buffer);
         
-        let expected = (csv
-            == "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n")
-            || (csv == "client,available,held,total,locked\n2,0.0,0.0,0.0,true\n1,250.0,0.0,250.0,false\n");
-
-        assert!(expected);
+        assert_eq!(csv, "client,available,held,total,locked\n1,250.0,0.0,250.0,false\n2,0.0,0.0,0.0,true\n");
     }
 
     async fn run_process_transactions_test(output1: &mut BufWriter<Vec<u8>>, output2: &mut BufWriter<Vec<u8>>, output3: &mut BufWriter<Vec<u8>>, rt: Arc<SpannedRuntime>) {
 
-        let mut input1 = r"
+        let input1 = r"
         type,client,tx,amount
         deposit,1,1,100
         withdrawal,1,2,50
@@ -88,7 +223,7 @@ mod tests {
         dispute,1,3"
             .as_bytes();
 
-        let mut input2 = r"
+        let input2 = r"
         type,client,tx,amount
         deposit,1,1,100
         withdrawal,1,2,50
@@ -101,7 +236,7 @@ mod tests {
         dispute,1,3"
             .as_bytes();
 
-        let mut input3 = r"
+        let input3 = r"
         type,client,tx,amount
         deposit,1,1,100
         withdrawal,1,2,50
@@ -114,19 +249,236 @@ mod tests {
         dispute,1,3"
             .as_bytes();
 
+        let options = Options::default();
+        let cancel = CancellationToken::default();
         let mut futures = FuturesUnordered::new();
         let rtc = rt.clone();
         let store1 = MemStore::default();
         let store2 = MemStore::default();
         let store3 = MemStore::default();
 
-        let fut1 = process_transactions(&mut input1, store1, output1, rtc.clone(), 2);
-        let fut2 = process_transactions(&mut input2, store2, output2, rtc.clone(), 2);
-        let fut3 = process_transactions(&mut input3, store3, output3, rtc, 2);
+        let fut1 = process_transactions(vec![Input::new("input1", Format::Csv, Box::new(input1))], store1, output1, None, rtc.clone(), &options, &cancel);
+        let fut2 = process_transactions(vec![Input::new("input2", Format::Csv, Box::new(input2))], store2, output2, None, rtc.clone(), &options, &cancel);
+        let fut3 = process_transactions(vec![Input::new("input3", Format::Csv, Box::new(input3))], store3, output3, None, rtc, &options, &cancel);
         futures.push(fut1);
         futures.push(fut2);
         futures.push(fut3);
 
-        while let Some(_) = futures.next().await {}
+        while futures.next().await.is_some() {}
+    }
+
+    #[test]
+    fn test_cancelled_processing() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        rt.block_on(async {
+            let input = "type,client,tx,amount\ndeposit,1,1,100\n".as_bytes();
+            let options = Options::default();
+            let cancel = CancellationToken::default();
+            cancel.cancel();
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &cancel).await.unwrap();
+        });
+
+        let buffer = output.into_inner();
+        // Nothing was read, so the report has no accounts.
+        assert_eq!(String::from_utf8_lossy(&buffer), "");
+    }
+
+    // CancelOnRead cancels the run once the reader gets to it, and never ends.
+    struct CancelOnRead(CancellationToken);
+
+    impl AsyncRead for CancelOnRead {
+        fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+            self.0.cancel();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_cancelled_mid_run() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        let summaries = rt.block_on(async {
+            let cancel = CancellationToken::default();
+            let rows = "type,client,tx,amount\ndeposit,1,1,100\ndeposit,2,2,50\nwithdrawal,1,3,30\n".as_bytes();
+            let input = Input::new("input", Format::Csv, Box::new(rows.chain(CancelOnRead(cancel.clone()))));
+            let options = Options { channel_capacity: 1, worker_count: 1, ..Options::default() };
+            process_transactions(vec![input], MemStore::default(), &mut output, None, rtc, &options, &cancel).await.unwrap()
+        });
+
+        // The rows read before the cancellation are drained and reported.
+        assert_eq!(summaries[0].rows, 3);
+        assert_eq!(summaries[0].applied, 3);
+        let buffer = output.into_inner();
+        assert_eq!(String::from_utf8_lossy(&buffer), "client,available,held,total,locked\n1,70.0,0.0,70.0,false\n2,50.0,0.0,50.0,false\n");
+    }
+
+    #[test]
+    fn test_fail_fast_processing() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        let summaries = rt.block_on(async {
+            let mut input = "type,client,tx,amount\n".to_string();
+            for tx in 1..=200 {
+                input.push_str(&format!("deposit,{},{},1.0\n", tx % 3, tx));
+            }
+            let options = Options { channel_capacity: 1, backpressure: Backpressure::FailFast, ..Options::default() };
+            let input = Input::new("input", Format::Csv, Box::new(std::io::Cursor::new(input.into_bytes())));
+            process_transactions(vec![input], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap()
+        });
+
+        // Transactions refused by a full queue are rejected, the run carries on and reports.
+        assert_eq!(summaries[0].rows, 200);
+        assert_eq!(summaries[0].applied + summaries[0].rejected, 200);
+        let report = String::from_utf8(output.into_inner()).unwrap();
+        let total: f32 = report.lines().skip(1).map(|line| line.split(',').nth(3).unwrap().parse::<f32>().unwrap()).sum();
+        assert_eq!(total, summaries[0].applied as f32);
+    }
+
+    #[test]
+    fn test_summary_skips_dispute_expiries() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        let summaries = rt.block_on(async {
+            let input = "type,client,tx,amount,timestamp\ndeposit,1,1,5.0,0\ndispute,1,1,,10\ndeposit,1,2,1.0,1000\n".as_bytes();
+            let options = Options { dispute_policy: DisputePolicy::new(None, Some(100), ExpiryAction::Resolve), ..Options::default() };
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap()
+        });
+
+        // The last deposit resolves the expired dispute, only the rows are counted.
+        assert_eq!(summaries[0].rows, 3);
+        assert_eq!(summaries[0].applied, 3);
+        assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "client,available,held,total,locked\n1,6.0,0.0,6.0,false\n");
+    }
+
+    #[test]
+    fn test_strict_header_aborts() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        let result = rt.block_on(async {
+            let input = "type,client,tx
+deposit,1,1
+".as_bytes();
+            let options = Options { read_mode: ReadMode::Strict, ..Options::default() };
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "Invalid header of input: line 1: missing column amount");
+        assert!(output.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_process_ndjson() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        rt.block_on(async {
+            let input = r#"{"type":"deposit","client":1,"tx":1,"amount":100}
+{"type":"withdrawal","client":1,"tx":2,"amount":40.5}
+{"type":"deposit","client":1,"tx":3,"amount":10}
+{"type":"dispute","client":1,"tx":3}"#
+                .as_bytes();
+            let options = Options { output_format: Format::Ndjson, ..Options::default() };
+            process_transactions(vec![Input::new("input", Format::Ndjson, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap();
+        });
+
+        let buffer = output.into_inner();
+        assert_eq!(String::from_utf8_lossy(&buffer), "{\"client\":1,\"available\":59.5,\"held\":10.0,\"total\":69.5,\"locked\":false}\n");
+    }
+
+    #[test]
+    fn test_process_multiple_inputs() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+
+        // The parallel csv parser gives the same results.
+        for parse_threads in [None, Some(2)] {
+            let rtc = rt.clone();
+            let mut output = BufWriter::new(Vec::<u8>::new());
+            let options = Options { parse_threads, ..Options::default() };
+
+            let summaries = rt.block_on(async {
+                let first = "type,client,tx,amount\ndeposit,1,1,100\ndeposito,1,2,5\nwithdrawal,2,3,10\n".as_bytes();
+                let second = r#"{"type":"deposit","client":2,"tx":4,"amount":20}
+{"type":"withdrawal","client":1,"tx":5,"amount":30}"#
+                    .as_bytes();
+                let inputs = vec![Input::new("first.csv", Format::Csv, Box::new(first)), Input::new("second.ndjson", Format::Ndjson, Box::new(second))];
+                process_transactions(inputs, MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap()
+            });
+
+            let buffer = output.into_inner();
+            assert_eq!(String::from_utf8_lossy(&buffer), "client,available,held,total,locked\n1,70.0,0.0,70.0,false\n2,20.0,0.0,20.0,false\n");
+            assert_eq!(summaries, vec![
+                FileSummary { file: "first.csv".to_string(), rows: 3, invalid: 1, applied: 1, rejected: 1, ..FileSummary::default() },
+                FileSummary { file: "second.ndjson".to_string(), rows: 2, applied: 2, ..FileSummary::default() },
+            ]);
+        }
+    }
+
+    #[test]
+    fn test_process_streaming() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        rt.block_on(async {
+            let input = "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,500\nwithdrawal,1,3,40\n".as_bytes();
+            let options = Options { stream: true, worker_count: 1, ..Options::default() };
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap();
+        });
+
+        let buffer = output.into_inner();
+        // One row per change, the rejected withdrawal changes nothing.
+        assert_eq!(String::from_utf8_lossy(&buffer), "seq,tx,client,available,held,total,locked\n0,1,1,100.0,0.0,100.0,false\n2,3,1,60.0,0.0,60.0,false\n");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_process_report_query() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        rt.block_on(async {
+            let input = "type,client,tx,amount\ndeposit,9,1,5\ndeposit,2,2,50\ndeposit,4,3,20\nwithdrawal,4,4,20\ndeposit,12,5,80\n".as_bytes();
+            let options = Options {
+                report: ReportQuery { order: ReportOrder::Total, non_zero_only: true, clients: vec![1..=10], ..ReportQuery::default() },
+                report_columns: vec![AccountColumn::Client, AccountColumn::Total],
+                ..Options::default()
+            };
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap();
+        });
+
+        let buffer = output.into_inner();
+        assert_eq!(String::from_utf8_lossy(&buffer), "client,total\n2,50.0\n9,5.0\n");
+    }
+
+    #[test]
+    fn test_process_extended_report() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+
+        rt.block_on(async {
+            let input = "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,500\ndeposit,1,3,10\ndispute,1,3,\nwithdrawal,2,4,5\n".as_bytes();
+            let options = Options { extended_report: true, output_format: Format::Ndjson, ..Options::default() };
+            process_transactions(vec![Input::new("input", Format::Csv, Box::new(input))], MemStore::default(), &mut output, None, rtc, &options, &CancellationToken::default()).await.unwrap();
+        });
+
+        let buffer = output.into_inner();
+        assert_eq!(
+            String::from_utf8_lossy(&buffer),
+            "{\"client\":1,\"available\":100.0,\"held\":10.0,\"total\":110.0,\"locked\":false,\"deposits\":2,\"deposited\":110.0,\"withdrawals\":0,\"withdrawn\":0.0,\
+             \"open_disputes\":1,\"disputed\":10.0,\"chargebacks\":0,\"charged_back\":0.0,\"rejected\":1,\"rejected_amount\":500.0}\n"
+        );
+    }
+}
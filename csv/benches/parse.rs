@@ -0,0 +1,45 @@
+// Rows per second of the sequential and the parallel csv readers, run with
+// cargo bench -p csv. Both run on the single worker runtime of the cli.
+use std::io::Cursor;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use csv::{dialect::Dialect, parallel::{read_csv_parallel, ParallelOptions}, reader::{read_csv_with_dialect, ReadMode}};
+use futures::StreamExt;
+use models::logger::create_span;
+
+const ROWS: u64 = 200_000;
+
+fn transactions() -> Vec<u8> {
+    let mut input = "type,client,tx,amount,timestamp\n".to_string();
+    for tx in 1..=ROWS {
+        let kind = if tx % 5 == 0 { "withdrawal" } else { "deposit" };
+        input.push_str(&format!("{},{},{},{}.{:04},{}\n", kind, tx % 1000, tx, tx % 500, tx % 10_000, 1_600_000_000 + tx));
+    }
+    input.into_bytes()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let rt = models::infra::get_runtime(1, 1, create_span()).unwrap();
+    let input = transactions();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(ROWS));
+    group.sample_size(10);
+
+    for mode in [ReadMode::Lenient, ReadMode::Strict] {
+        group.bench_function(BenchmarkId::new(format!("{:?}/sequential", mode), 1), |b| b.iter(|| rt.block_on(async {
+            let mut reader = Cursor::new(input.clone());
+            read_csv_with_dialect(&mut reader, mode, Dialect::default()).await.count().await
+        })));
+        for threads in [2, 4, 8] {
+            group.bench_function(BenchmarkId::new(format!("{:?}/parallel", mode), threads), |b| b.iter(|| rt.block_on(async {
+                let mut reader = Cursor::new(input.clone());
+                let options = ParallelOptions::new(threads).with_chunk_size(1 << 20);
+                read_csv_parallel(&mut reader, mode, Dialect::default(), options).await.count().await
+            })));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
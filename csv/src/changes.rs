@@ -0,0 +1,103 @@
+use models::{account::AccountChange, error::Error, ids::{ClientId, TransactionId}};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{format::Format, ndjson::write_line, writer::Writer};
+
+// ChangeRow is an account row with the transaction which produced it.
+#[derive(Debug, Serialize)]
+struct ChangeRow {
+    seq: Option<u64>,
+    tx: TransactionId,
+    client: ClientId,
+    available: f32,
+    held: f32,
+    total: f32,
+    locked: bool,
+}
+
+impl From<AccountChange> for ChangeRow {
+    fn from(change: AccountChange) -> Self {
+        let mut account = change.account;
+        account.to_max_display_precision();
+        Self {
+            seq: change.sequence,
+            tx: change.tx,
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
+// ChangeWriter writes account rows incrementally as accounts change. Every row
+// is flushed right away so readers of a pipe see it as it happens.
+pub enum ChangeWriter<'a> {
+    Csv(Box<csv_async::AsyncSerializer<&'a mut Writer>>),
+    Ndjson(&'a mut Writer),
+}
+
+impl<'a> ChangeWriter<'a> {
+    // new fails for columnar formats, which can not be written row by row.
+    pub fn new(writer: &'a mut Writer, format: Format) -> Result<Self, Error> {
+        match format {
+            Format::Csv => Ok(ChangeWriter::Csv(Box::new(csv_async::AsyncSerializer::from_writer(writer)))),
+            Format::Ndjson => Ok(ChangeWriter::Ndjson(writer)),
+            Format::Parquet | Format::Arrow => Err(Error::from(format!("account changes can not be streamed as {:?}", format))),
+        }
+    }
+
+    pub async fn write(&mut self, change: AccountChange) -> Result<(), Error> {
+        let row = ChangeRow::from(change);
+        match self {
+            ChangeWriter::Csv(writer) => {
+                writer.serialize(row).await?;
+                writer.flush().await?;
+            },
+            ChangeWriter::Ndjson(writer) => {
+                write_line(&mut **writer, &row).await?;
+                writer.flush().await?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use models::{account::{Account, AccountChange}, ids::TransactionId, logger::create_span};
+    use tokio::io::BufWriter;
+
+    use crate::format::Format;
+
+    use super::ChangeWriter;
+
+    #[test]
+    fn test_change_writer() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let changes = [
+                AccountChange { sequence: Some(0), tx: TransactionId(1), account: Account::load(1, 10.0, 0.0, false) },
+                AccountChange { sequence: Some(2), tx: TransactionId(1), account: Account::load(1, 0.0, 10.0, false) },
+            ];
+            for (format, expected) in [
+                (Format::Csv, "seq,tx,client,available,held,total,locked\n0,1,1,10.0,0.0,10.0,false\n2,1,1,0.0,10.0,10.0,false\n"),
+                (Format::Ndjson, "{\"seq\":0,\"tx\":1,\"client\":1,\"available\":10.0,\"held\":0.0,\"total\":10.0,\"locked\":false}\n\
+                                  {\"seq\":2,\"tx\":1,\"client\":1,\"available\":0.0,\"held\":10.0,\"total\":10.0,\"locked\":false}\n"),
+            ] {
+                let mut writer = BufWriter::new(Vec::<u8>::new());
+                let mut changes_writer = ChangeWriter::new(&mut writer, format).unwrap();
+                // Rows are visible in the underlying buffer as soon as they are written.
+                changes_writer.write(changes[0].clone()).await.unwrap();
+                changes_writer.write(changes[1].clone()).await.unwrap();
+                drop(changes_writer);
+                assert_eq!(String::from_utf8_lossy(writer.get_ref()), expected);
+            }
+            assert!(ChangeWriter::new(&mut BufWriter::new(Vec::<u8>::new()), Format::Parquet).is_err());
+        })
+    }
+}
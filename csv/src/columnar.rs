@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, BooleanArray, Float32Array, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use futures::StreamExt;
+use models::{account::Account, error::Error, outcome::Outcome, stats::ExtendedAccount};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{columns::AccountColumn, format::Format, writer::Writer};
+
+// write_accounts_columnar writes the selected columns of the accounts as a
+// Parquet or Arrow IPC file with typed columns.
+pub async fn write_accounts_columnar(writer: &mut Writer, format: Format, account_stream: impl futures::Stream<Item = Account> + Send + Unpin, columns: &[AccountColumn]) -> Result<(), Error> {
+    let accounts = account_stream
+        .map(|mut account| {
+            account.to_max_display_precision();
+            account
+        })
+        .collect::<Vec<_>>()
+        .await;
+    let fields = columns.iter().map(|column| account_field(*column)).collect::<Vec<_>>();
+    let arrays = columns.iter().map(|column| account_array(*column, &accounts)).collect::<Vec<_>>();
+    write_batch(writer, format, fields, arrays).await
+}
+
+// write_extended_columnar writes the account report with the activity statistics of each client.
+pub async fn write_extended_columnar(writer: &mut Writer, format: Format, mut extended_stream: impl futures::Stream<Item = ExtendedAccount> + Send + Unpin) -> Result<(), Error> {
+    let mut accounts = Vec::new();
+    let mut stats = Vec::new();
+    while let Some(ExtendedAccount { mut account, stats: mut account_stats }) = extended_stream.next().await {
+        account.to_max_display_precision();
+        account_stats.to_max_display_precision();
+        accounts.push(account);
+        stats.push(account_stats);
+    }
+
+    let mut fields = AccountColumn::ALL.iter().map(|column| account_field(*column)).collect::<Vec<_>>();
+    let mut arrays = AccountColumn::ALL.iter().map(|column| account_array(*column, &accounts)).collect::<Vec<_>>();
+    for (field, array) in [
+        count("deposits", stats.iter().map(|s| s.deposits)),
+        sum("deposited", stats.iter().map(|s| s.deposited)),
+        count("withdrawals", stats.iter().map(|s| s.withdrawals)),
+        sum("withdrawn", stats.iter().map(|s| s.withdrawn)),
+        count("open_disputes", stats.iter().map(|s| s.open_disputes)),
+        sum("disputed", stats.iter().map(|s| s.disputed)),
+        count("chargebacks", stats.iter().map(|s| s.chargebacks)),
+        sum("charged_back", stats.iter().map(|s| s.charged_back)),
+        count("rejected", stats.iter().map(|s| s.rejected)),
+        sum("rejected_amount", stats.iter().map(|s| s.rejected_amount)),
+    ] {
+        fields.push(field);
+        arrays.push(array);
+    }
+    write_batch(writer, format, fields, arrays).await
+}
+
+// write_outcomes_columnar writes the outcomes with the same columns as write_outcomes_csv.
+pub async fn write_outcomes_columnar(writer: &mut Writer, format: Format, outcome_stream: impl futures::Stream<Item = Outcome> + Send + Unpin) -> Result<(), Error> {
+    let outcomes = outcome_stream.collect::<Vec<_>>().await;
+    let strings = |value: fn(&Outcome) -> Option<String>| -> ArrayRef {
+        Arc::new(StringArray::from(outcomes.iter().map(value).collect::<Vec<_>>()))
+    };
+
+    let fields = vec![
+        Field::new("seq", DataType::UInt64, true),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("client", DataType::UInt32, false),
+        Field::new("tx", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("timestamp", DataType::UInt64, true),
+        Field::new("reference", DataType::Utf8, true),
+        Field::new("merchant", DataType::Utf8, true),
+        Field::new("description", DataType::Utf8, true),
+        Field::new("metadata", DataType::Utf8, true),
+        Field::new("rule", DataType::Utf8, true),
+        Field::new("reason", DataType::Utf8, true),
+    ];
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from(outcomes.iter().map(|o| o.sequence).collect::<Vec<_>>())),
+        Arc::new(StringArray::from(outcomes.iter().map(|o| name(&o.kind)).collect::<Result<Vec<_>, _>>()?)),
+        Arc::new(UInt32Array::from(outcomes.iter().map(|o| o.client.0).collect::<Vec<_>>())),
+        Arc::new(UInt64Array::from(outcomes.iter().map(|o| o.tx.0).collect::<Vec<_>>())),
+        Arc::new(StringArray::from(outcomes.iter().map(|o| name(&o.status)).collect::<Result<Vec<_>, _>>()?)),
+        Arc::new(UInt64Array::from(outcomes.iter().map(|o| o.timestamp).collect::<Vec<_>>())),
+        strings(|o| o.reference.clone()),
+        strings(|o| o.merchant.clone()),
+        strings(|o| o.description.clone()),
+        strings(|o| o.metadata_string()),
+        strings(|o| o.rule.clone()),
+        strings(|o| o.reason.clone()),
+    ];
+    write_batch(writer, format, fields, arrays).await
+}
+
+fn count(name: &str, values: impl Iterator<Item = u64>) -> (Field, ArrayRef) {
+    (Field::new(name, DataType::UInt64, false), Arc::new(UInt64Array::from(values.collect::<Vec<_>>())))
+}
+
+fn sum(name: &str, values: impl Iterator<Item = f32>) -> (Field, ArrayRef) {
+    (Field::new(name, DataType::Float32, false), Arc::new(Float32Array::from(values.collect::<Vec<_>>())))
+}
+
+fn account_field(column: AccountColumn) -> Field {
+    let data_type = match column {
+        AccountColumn::Client => DataType::UInt32,
+        AccountColumn::Available | AccountColumn::Held | AccountColumn::Total => DataType::Float32,
+        AccountColumn::Locked => DataType::Boolean,
+    };
+    Field::new(column.name(), data_type, false)
+}
+
+fn account_array(column: AccountColumn, accounts: &[Account]) -> ArrayRef {
+    match column {
+        AccountColumn::Client => Arc::new(UInt32Array::from(accounts.iter().map(|a| a.client.0).collect::<Vec<_>>())),
+        AccountColumn::Available => Arc::new(Float32Array::from(accounts.iter().map(|a| a.available).collect::<Vec<_>>())),
+        AccountColumn::Held => Arc::new(Float32Array::from(accounts.iter().map(|a| a.held).collect::<Vec<_>>())),
+        AccountColumn::Total => Arc::new(Float32Array::from(accounts.iter().map(|a| a.total).collect::<Vec<_>>())),
+        AccountColumn::Locked => Arc::new(BooleanArray::from(accounts.iter().map(|a| a.locked).collect::<Vec<_>>())),
+    }
+}
+
+// name is the serialized name of a unit enum variant, e.g. deposit or auto_resolved.
+fn name<T: Serialize>(value: &T) -> Result<String, Error> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => Ok(name),
+        Ok(other) => Err(Error::from(format!("expected a name, got {}", other))),
+        Err(e) => Err(Error::from(e.to_string())),
+    }
+}
+
+// write_batch encodes the columns as a single record batch file. Both formats
+// need the whole file before writing its footer, so it is encoded in memory.
+async fn write_batch(writer: &mut Writer, format: Format, fields: Vec<Field>, arrays: Vec<ArrayRef>) -> Result<(), Error> {
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), arrays).map_err(columnar_error)?;
+    let mut buffer = Vec::new();
+    match format {
+        Format::Parquet => {
+            let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let mut parquet = ArrowWriter::try_new(&mut buffer, schema, Some(properties)).map_err(columnar_error)?;
+            parquet.write(&batch).map_err(columnar_error)?;
+            parquet.close().map_err(columnar_error)?;
+        },
+        Format::Arrow => {
+            let mut ipc = arrow_ipc::writer::FileWriter::try_new(&mut buffer, &schema).map_err(columnar_error)?;
+            ipc.write(&batch).map_err(columnar_error)?;
+            ipc.finish().map_err(columnar_error)?;
+        },
+        other => return Err(Error::from(format!("{:?} is not a columnar format", other))),
+    }
+    writer.write_all(&buffer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn columnar_error(e: impl std::fmt::Display) -> Error {
+    Error::from(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use arrow_array::{cast::AsArray, types::{Float32Type, UInt32Type, UInt64Type}, RecordBatch};
+    use models::{account::Account, ids::ClientId, logger::create_span, outcome::{Outcome, OutcomeStatus}, stats::{AccountStats, ExtendedAccount}, transactions::{Transaction, TransactionKind}};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{columns::AccountColumn, format::Format};
+    use super::{write_accounts_columnar, write_extended_columnar, write_outcomes_columnar};
+
+    fn read_batch(format: Format, buffer: Vec<u8>) -> RecordBatch {
+        match format {
+            Format::Parquet => ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buffer)).unwrap().build().unwrap().next().unwrap().unwrap(),
+            _ => arrow_ipc::reader::FileReader::try_new(Cursor::new(buffer), None).unwrap().next().unwrap().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_write_accounts_columnar() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for format in [Format::Parquet, Format::Arrow] {
+                let accounts = futures::stream::iter(vec![Account::load(1, 5.36, 1.58, false), Account::load(2, 8.19, 3.08, true)]);
+                let mut writer = Cursor::new(Vec::<u8>::new());
+                write_accounts_columnar(&mut writer, format, accounts, &[AccountColumn::Client, AccountColumn::Total, AccountColumn::Locked]).await.unwrap();
+
+                let batch = read_batch(format, writer.into_inner());
+                let names = batch.schema().fields().iter().map(|f| f.name().clone()).collect::<Vec<_>>();
+                assert_eq!(names, vec!["client", "total", "locked"]);
+                assert_eq!(batch.column(0).as_primitive::<UInt32Type>().values().to_vec(), vec![1, 2]);
+                assert_eq!(batch.column(1).as_primitive::<Float32Type>().values().to_vec(), vec![6.94, 11.27]);
+                assert_eq!(batch.column(2).as_boolean().iter().collect::<Vec<_>>(), vec![Some(false), Some(true)]);
+            }
+        })
+    }
+
+    #[test]
+    fn test_write_extended_columnar() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let stats = AccountStats { deposits: 2, deposited: 6.94, rejected: 1, rejected_amount: 100.0, ..AccountStats::new(ClientId(1)) };
+            let extended = futures::stream::iter(vec![ExtendedAccount { account: Account::load(1, 5.36, 1.58, false), stats }]);
+            let mut writer = Cursor::new(Vec::<u8>::new());
+            write_extended_columnar(&mut writer, Format::Parquet, extended).await.unwrap();
+
+            let batch = read_batch(Format::Parquet, writer.into_inner());
+            let names = batch.schema().fields().iter().map(|f| f.name().clone()).collect::<Vec<_>>();
+            assert_eq!(names, vec![
+                "client", "available", "held", "total", "locked", "deposits", "deposited", "withdrawals", "withdrawn",
+                "open_disputes", "disputed", "chargebacks", "charged_back", "rejected", "rejected_amount",
+            ]);
+            assert_eq!(batch.column(5).as_primitive::<UInt64Type>().value(0), 2);
+            assert_eq!(batch.column(14).as_primitive::<Float32Type>().value(0), 100.0);
+        })
+    }
+
+    #[test]
+    fn test_write_outcomes_columnar() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut deposit = Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000);
+            deposit.sequence = Some(0);
+            let outcomes = futures::stream::iter(vec![
+                Outcome::new(&deposit, OutcomeStatus::Applied, None),
+                Outcome::new(&Transaction::new(TransactionKind::ChargeBack, 1, 1, None), OutcomeStatus::AutoChargedBack, Some("Dispute deadline exceeded".to_string())),
+            ]);
+            let mut writer = Cursor::new(Vec::<u8>::new());
+            write_outcomes_columnar(&mut writer, Format::Arrow, outcomes).await.unwrap();
+
+            let batch = read_batch(Format::Arrow, writer.into_inner());
+            assert_eq!(batch.num_columns(), 12);
+            assert_eq!(batch.column(0).as_primitive::<UInt64Type>().iter().collect::<Vec<_>>(), vec![Some(0), None]);
+            assert_eq!(batch.column(1).as_string::<i32>().iter().collect::<Vec<_>>(), vec![Some("deposit"), Some("chargeback")]);
+            assert_eq!(batch.column(4).as_string::<i32>().iter().collect::<Vec<_>>(), vec![Some("applied"), Some("auto_charged_back")]);
+            assert_eq!(batch.column(11).as_string::<i32>().iter().collect::<Vec<_>>(), vec![None, Some("Dispute deadline exceeded")]);
+        })
+    }
+
+    #[test]
+    fn test_write_csv_as_columnar_fails() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut writer = Cursor::new(Vec::<u8>::new());
+            let result = write_accounts_columnar(&mut writer, Format::Csv, futures::stream::iter(vec![]), &AccountColumn::ALL).await;
+            assert!(result.is_err());
+        })
+    }
+}
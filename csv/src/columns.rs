@@ -0,0 +1,131 @@
+use futures::StreamExt;
+use models::{account::Account, error::Error};
+use tokio::io::AsyncWriteExt;
+
+use crate::writer::Writer;
+
+// AccountColumn is a column of the account report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountColumn {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+impl AccountColumn {
+    pub const ALL: [AccountColumn; 5] = [AccountColumn::Client, AccountColumn::Available, AccountColumn::Held, AccountColumn::Total, AccountColumn::Locked];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        AccountColumn::ALL.into_iter().find(|column| column.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountColumn::Client => "client",
+            AccountColumn::Available => "available",
+            AccountColumn::Held => "held",
+            AccountColumn::Total => "total",
+            AccountColumn::Locked => "locked",
+        }
+    }
+
+    // value is the column of the account in json, which reads the same in csv.
+    fn value(&self, account: &Account) -> Result<String, Error> {
+        let value = match self {
+            AccountColumn::Client => serde_json::to_string(&account.client),
+            AccountColumn::Available => serde_json::to_string(&account.available),
+            AccountColumn::Held => serde_json::to_string(&account.held),
+            AccountColumn::Total => serde_json::to_string(&account.total),
+            AccountColumn::Locked => serde_json::to_string(&account.locked),
+        };
+        value.map_err(|e| Error::from(e.to_string()))
+    }
+}
+
+// write_csv_columns writes the selected columns of the accounts in the given order.
+pub async fn write_csv_columns(writer: &mut Writer, mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin, columns: &[AccountColumn]) -> Result<(), Error> {
+    let mut writer = csv_async::AsyncWriter::from_writer(writer);
+    let mut header = true;
+
+    while let Some(mut account) = account_stream.next().await {
+        if header {
+            writer.write_record(columns.iter().map(|c| c.name())).await?;
+            header = false;
+        }
+        account.to_max_display_precision();
+        let record = columns.iter().map(|c| c.value(&account)).collect::<Result<Vec<_>, _>>()?;
+        writer.write_record(record).await?;
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+// write_ndjson_columns writes an object with the selected keys of each account.
+pub async fn write_ndjson_columns(writer: &mut Writer, mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin, columns: &[AccountColumn]) -> Result<(), Error> {
+    while let Some(mut account) = account_stream.next().await {
+        account.to_max_display_precision();
+        let fields = columns.iter()
+            .map(|c| c.value(&account).map(|value| format!("\"{}\":{}", c.name(), value)))
+            .collect::<Result<Vec<_>, _>>()?;
+        writer.write_all(format!("{{{}}}\n", fields.join(",")).as_bytes()).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use models::{account::Account, logger::create_span};
+    use tokio::io::BufWriter;
+
+    use crate::{ndjson::write_ndjson, writer::write_csv};
+    use super::{write_csv_columns, write_ndjson_columns, AccountColumn};
+
+    fn accounts() -> Vec<Account> {
+        vec![Account::load(1, 5.36, 1.58, false), Account::load(2, 250.0, 0.0, true)]
+    }
+
+    #[test]
+    fn test_write_columns() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let columns = [AccountColumn::Total, AccountColumn::Client, AccountColumn::Locked];
+
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_csv_columns(&mut writer, futures::stream::iter(accounts()), &columns).await.unwrap();
+            assert_eq!(String::from_utf8_lossy(writer.get_ref()), "total,client,locked\n6.94,1,false\n250.0,2,true\n");
+
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_ndjson_columns(&mut writer, futures::stream::iter(accounts()), &columns).await.unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(writer.get_ref()),
+                "{\"total\":6.94,\"client\":1,\"locked\":false}\n{\"total\":250.0,\"client\":2,\"locked\":true}\n"
+            );
+            assert_eq!(AccountColumn::from_name("held"), Some(AccountColumn::Held));
+            assert_eq!(AccountColumn::from_name("balance"), None);
+        })
+    }
+
+    #[test]
+    fn test_all_columns_match_report() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut expected = BufWriter::new(Vec::<u8>::new());
+            write_csv(&mut expected, futures::stream::iter(accounts())).await.unwrap();
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_csv_columns(&mut writer, futures::stream::iter(accounts()), &AccountColumn::ALL).await.unwrap();
+            assert_eq!(writer.get_ref(), expected.get_ref());
+
+            let mut expected = BufWriter::new(Vec::<u8>::new());
+            write_ndjson(&mut expected, futures::stream::iter(accounts())).await.unwrap();
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_ndjson_columns(&mut writer, futures::stream::iter(accounts()), &AccountColumn::ALL).await.unwrap();
+            assert_eq!(writer.get_ref(), expected.get_ref());
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use async_compression::tokio::{bufread::{GzipDecoder, ZstdDecoder}, write::{GzipEncoder, ZstdEncoder}};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader};
+
+use crate::writer::Writer;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()).as_deref() {
+            Some("gz" | "gzip") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+// decompress detects the compression of reader from its first bytes. The input
+// is decompressed as it is read, never buffered whole.
+pub async fn decompress<'a, R: AsyncRead + Send + Unpin + 'a>(reader: R) -> Box<dyn AsyncRead + Send + Unpin + 'a> {
+    let mut reader = BufReader::new(reader);
+    // A read error is left for the first read of the caller to report.
+    let compression = reader.fill_buf().await.map_or(Compression::None, Compression::from_magic);
+    match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            // Concatenated gzip files are valid gzip, e.g. appended daily files.
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        },
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        },
+    }
+}
+
+// compress wraps writer with an encoder for compression. The returned writer
+// must be shut down to write the end of the compressed stream.
+pub fn compress<W: AsyncWrite + Send + Sync + Unpin + 'static>(writer: W, compression: Compression) -> Box<Writer> {
+    match compression {
+        Compression::None => Box::new(writer),
+        Compression::Gzip => Box::new(GzipEncoder::new(writer)),
+        Compression::Zstd => Box::new(ZstdEncoder::new(writer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use models::{account::Account, logger::create_span};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::writer::write_csv;
+
+    use super::{compress, decompress, Compression};
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path(Path::new("transactions.csv.gz")), Compression::Gzip);
+        assert_eq!(Compression::from_path(Path::new("transactions.csv.ZST")), Compression::Zstd);
+        assert_eq!(Compression::from_path(Path::new("transactions.csv")), Compression::None);
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+                let path = std::env::temp_dir().join(format!("compression-{}-{:?}.csv", std::process::id(), compression));
+                let mut writer = compress(tokio::fs::File::create(&path).await.unwrap(), compression);
+                write_csv(&mut writer, futures::stream::iter(vec![Account::load(1, 1.5, 0.0, false)])).await.unwrap();
+                writer.shutdown().await.unwrap();
+
+                let file = tokio::fs::read(&path).await.unwrap();
+                assert_eq!(Compression::from_magic(&file), compression);
+                let mut csv = String::new();
+                decompress(file.as_slice()).await.read_to_string(&mut csv).await.unwrap();
+                assert_eq!(csv, "client,available,held,total,locked\n1,1.5,0.0,1.5,false\n");
+                std::fs::remove_file(path).unwrap();
+            }
+        })
+    }
+}
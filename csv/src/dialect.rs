@@ -0,0 +1,104 @@
+use std::{collections::BTreeMap, path::Path};
+
+use models::error::{Error, ErrorKind};
+use serde::Deserialize;
+
+use crate::reader::TRANSACTION_COLUMNS;
+
+// Dialect describes the csv layout of an input and maps its columns to the
+// transaction fields, e.g. in toml
+//   delimiter = ";"
+//   has_headers = false
+//   columns = ["transaction_id", "customer", "type", "amount"]
+//   [mapping]
+//   transaction_id = "tx"
+//   customer = "client"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    pub has_headers: bool,
+    // Column names of headerless input in order, the transaction fields by default.
+    pub columns: Vec<String>,
+    // Input column name to transaction field, unmapped columns keep their name.
+    pub mapping: BTreeMap<String, String>,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self { delimiter: ',', quote: '"', has_headers: true, columns: Vec::new(), mapping: BTreeMap::new() }
+    }
+}
+
+impl Dialect {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        let dialect: Dialect = toml::from_str(&content)
+            .map_err(|e| dialect_error(format!("invalid dialect file {}: {}", path.display(), e)))?;
+        dialect.validate()?;
+        Ok(dialect)
+    }
+
+    // validate checks the delimiter and quote fit a byte and the mapping targets transaction fields.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.delimiter.is_ascii() || !self.quote.is_ascii() {
+            return Err(dialect_error("delimiter and quote must be ascii characters".to_string()));
+        }
+        if self.delimiter == self.quote {
+            return Err(dialect_error("delimiter and quote must differ".to_string()));
+        }
+        match self.mapping.values().find(|field| !TRANSACTION_COLUMNS.contains(&field.as_str())) {
+            Some(field) => Err(dialect_error(format!("unknown transaction field {}", field))),
+            None => Ok(()),
+        }
+    }
+
+    // headers returns the transaction field names of the input columns, read
+    // holds the header row of inputs with headers.
+    pub(crate) fn headers(&self, read: &csv_async::StringRecord) -> csv_async::StringRecord {
+        let names = if self.has_headers {
+            read.iter().map(str::to_string).collect::<Vec<_>>()
+        } else if self.columns.is_empty() {
+            TRANSACTION_COLUMNS.iter().map(|c| c.to_string()).collect()
+        } else {
+            self.columns.clone()
+        };
+        let mut headers = names.iter()
+            .map(|name| self.mapping.get(name).unwrap_or(name).as_str())
+            .collect::<csv_async::StringRecord>();
+        headers.set_position(read.position().cloned());
+        headers
+    }
+}
+
+fn dialect_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dialect;
+
+    #[test]
+    fn test_dialect_config() {
+        let dialect: Dialect = toml::from_str(r#"
+            delimiter = ";"
+            has_headers = false
+            columns = ["transaction_id", "customer", "type", "amount"]
+            [mapping]
+            transaction_id = "tx"
+            customer = "client"
+        "#).unwrap();
+        assert_eq!(dialect.delimiter, ';');
+        assert_eq!(dialect.quote, '"');
+        assert!(dialect.validate().is_ok());
+
+        let headers = dialect.headers(&csv_async::StringRecord::new());
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec!["tx", "client", "type", "amount"]);
+
+        assert!(toml::from_str::<Dialect>("separator = \";\"").is_err());
+        assert!(Dialect { delimiter: '€', ..Dialect::default() }.validate().is_err());
+        assert!(toml::from_str::<Dialect>("[mapping]\nid = \"transaction\"").unwrap().validate().is_err());
+    }
+}
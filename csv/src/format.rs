@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::compression::Compression;
+
+// Format of a transaction input or report output. Parquet and Arrow are
+// columnar output formats only.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Csv,
+    // One JSON object per line.
+    Ndjson,
+    Parquet,
+    // Arrow IPC file.
+    Arrow,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "parquet" => Some(Format::Parquet),
+            "arrow" => Some(Format::Arrow),
+            _ => None,
+        }
+    }
+
+    pub fn is_columnar(&self) -> bool {
+        matches!(self, Format::Parquet | Format::Arrow)
+    }
+
+    // from_path picks the format from the file extension, None when it is not a known one.
+    // A compression extension is skipped, e.g. transactions.csv.gz is csv.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = match Compression::from_path(path) {
+            Compression::None => path,
+            _ => Path::new(path.file_stem()?),
+        };
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| Format::from_name(&ext.to_ascii_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Format;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("transactions.csv")), Some(Format::Csv));
+        assert_eq!(Format::from_path(Path::new("transactions.ndjson")), Some(Format::Ndjson));
+        assert_eq!(Format::from_path(Path::new("out/transactions.JSONL")), Some(Format::Ndjson));
+        assert_eq!(Format::from_path(Path::new("accounts.parquet")), Some(Format::Parquet));
+        assert_eq!(Format::from_path(Path::new("accounts.arrow")), Some(Format::Arrow));
+        assert_eq!(Format::from_path(Path::new("transactions.txt")), None);
+        assert_eq!(Format::from_path(Path::new("transactions")), None);
+        assert_eq!(Format::from_path(Path::new("transactions.ndjson.gz")), Some(Format::Ndjson));
+        assert_eq!(Format::from_path(Path::new("transactions.zst")), None);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod changes;
+pub mod columnar;
+pub mod columns;
+pub mod compression;
+pub mod dialect;
+pub mod format;
+pub mod ndjson;
+pub mod parallel;
 pub mod reader;
 pub mod writer;
\ No newline at end of file
@@ -0,0 +1,202 @@
+use futures::StreamExt;
+use models::{account::Account, error::Error, outcome::Outcome, stats::ExtendedAccount, transactions::Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_stream::wrappers::LinesStream;
+
+use crate::{compression::decompress, reader::{check_transaction, ReadMode, Reader, ValidationError, TRANSACTION_COLUMNS}, writer::{ExtendedRow, Writer}};
+
+// read_ndjson reads one transaction per line, blank lines are skipped.
+// Keys other than the transaction fields are read as metadata, like extra csv columns.
+// Gzip and zstd input is decompressed on the fly.
+pub async fn read_ndjson(reader: &mut Reader) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
+    read_ndjson_with_mode(reader, ReadMode::Lenient).await
+}
+
+// read_ndjson_with_mode validates transactions according to mode like csv records,
+// strict mode checks the amount required by each transaction kind.
+pub async fn read_ndjson_with_mode(reader: &mut Reader, mode: ReadMode) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
+    LinesStream::new(BufReader::new(decompress(reader).await).lines())
+        .enumerate()
+        .filter(|(_, line)| futures::future::ready(!matches!(line, Ok(line) if line.trim().is_empty())))
+        .map(move |(index, line)| {
+            let line = line?;
+            let transaction = parse_transaction(&line).map_err(|e| located_error(&e, index as u64 + 1))?;
+            check_transaction(&transaction, mode).map_err(|(_, message)| ValidationError::new(index as u64 + 1, None, message))?;
+            Ok(transaction)
+        })
+}
+
+// located_error reports json errors with the input line, and the column when the line is not valid json.
+fn located_error(err: &serde_json::Error, line: u64) -> ValidationError {
+    let message = err.to_string();
+    // Drop the position serde_json appends, it is relative to the single line parsed.
+    let message = message.rfind(" at line ").map_or(message.as_str(), |end| &message[..end]).to_string();
+    let column = Some(err.column()).filter(|_| err.is_syntax() || err.is_eof());
+    ValidationError::new(line, column, message)
+}
+
+fn parse_transaction(line: &str) -> Result<Transaction, serde_json::Error> {
+    let value = serde_json::from_str::<Value>(line)?;
+    let mut transaction = Transaction::deserialize(&value)?;
+    if let Value::Object(fields) = value {
+        transaction.metadata = fields.into_iter()
+            .filter(|(key, value)| !value.is_null() && !TRANSACTION_COLUMNS.contains(&key.as_str()))
+            .map(|(key, value)| match value {
+                Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            })
+            .collect();
+    }
+    Ok(transaction)
+}
+
+pub async fn write_ndjson(writer: &mut Writer, mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin) -> Result<(), Error> {
+    while let Some(mut account) = account_stream.next().await {
+        account.to_max_display_precision();
+        write_line(writer, &account).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn write_extended_ndjson(writer: &mut Writer, mut extended_stream: impl futures::Stream<Item = ExtendedAccount> + Send + Unpin) -> Result<(), Error> {
+    while let Some(extended) = extended_stream.next().await {
+        write_line(writer, &ExtendedRow::from(extended)).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn write_outcomes_ndjson(writer: &mut Writer, mut outcome_stream: impl futures::Stream<Item = Outcome> + Send + Unpin) -> Result<(), Error> {
+    while let Some(outcome) = outcome_stream.next().await {
+        write_line(writer, &outcome).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+// write_transactions_ndjson writes transactions in the input format, metadata included.
+pub async fn write_transactions_ndjson(writer: &mut Writer, transactions: Vec<Transaction>) -> Result<(), Error> {
+    for transaction in transactions {
+        let mut value = serde_json::to_value(&transaction).map_err(|e| Error::from(e.to_string()))?;
+        if let Value::Object(fields) = &mut value {
+            for (key, metadata) in &transaction.metadata {
+                fields.entry(key.clone()).or_insert_with(|| Value::String(metadata.clone()));
+            }
+        }
+        write_line(writer, &value).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+pub(crate) async fn write_line<T: Serialize>(writer: &mut Writer, value: &T) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(value).map_err(|e| Error::from(e.to_string()))?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use models::{account::Account, logger::create_span, outcome::{Outcome, OutcomeStatus}, transactions::{Transaction, TransactionKind}};
+    use tokio::io::BufWriter;
+
+    use crate::reader::ReadMode;
+    use super::{read_ndjson, read_ndjson_with_mode, write_ndjson, write_outcomes_ndjson, write_transactions_ndjson};
+
+    #[test]
+    fn test_read_ndjson() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut input = r#"{"type":"deposit","client":1,"tx":1,"amount":2.5,"timestamp":1000,"channel":"web","attempt":2}
+
+{"type":"dispute","client":"1","tx":1,"amount":null}
+{"type":"deposito","client":1,"tx":2,"amount":1.0}
+{"type":"release","client":1,"tx":1}
+{"type":"deposit","client":1,"#
+                .as_bytes();
+
+            let result = read_ndjson(&mut input)
+                .await
+                .map(|tx| tx.map_err(|e| e.to_string()))
+                .collect::<Vec<_>>()
+                .await;
+
+            let deposit = Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.5))
+                .with_timestamp(1000)
+                .with_metadata("attempt", "2")
+                .with_metadata("channel", "web");
+            assert_eq!(result[0], Ok(deposit));
+            assert_eq!(result[1], Ok(Transaction::new(TransactionKind::Dispute, 1, 1, None)));
+            assert!(result[2].as_ref().unwrap_err().starts_with("line 4: unknown variant `deposito`"));
+            assert_eq!(result[3], Err("line 5: release is an admin operation and can not be read from input".to_string()));
+            assert_eq!(result[4], Err("line 6, column 29: EOF while parsing a value".to_string()));
+            assert_eq!(result.len(), 5);
+        })
+    }
+
+    #[test]
+    fn test_read_ndjson_strict() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut input = r#"{"type":"deposit","client":1,"tx":1,"amount":2.5}
+{"type":"deposit","client":1,"tx":2,"amount":-1.0}
+{"type":"dispute","client":1,"tx":1,"amount":2.5}
+{"type":"dispute","client":1,"tx":1}
+{"type":"withdrawal","client":1,"tx":3}"#
+                .as_bytes();
+
+            let result = read_ndjson_with_mode(&mut input, ReadMode::Strict)
+                .await
+                .map(|tx| tx.map_err(|e| e.to_string()))
+                .collect::<Vec<_>>()
+                .await;
+
+            assert_eq!(result, vec![
+                Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.5))),
+                Err("line 2: amount must be positive, found -1".to_string()),
+                Err("line 3: amount is not allowed for dispute".to_string()),
+                Ok(Transaction::new(TransactionKind::Dispute, 1, 1, None)),
+                Err("line 5: amount is required for withdrawal".to_string()),
+            ]);
+        })
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let accounts = futures::stream::iter(vec![Account::load(1, 5.36, 1.58, false)]);
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_ndjson(&mut writer, accounts).await.unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&writer.into_inner()),
+                "{\"client\":1,\"available\":5.36,\"held\":1.58,\"total\":6.94,\"locked\":false}\n"
+            );
+
+            let mut dispute = Transaction::new(TransactionKind::Dispute, 1, 1, None).with_metadata("region", "eu");
+            dispute.sequence = Some(3);
+            let outcomes = futures::stream::iter(vec![Outcome::new(&dispute, OutcomeStatus::Rejected, Some("Unknown transaction".to_string()))]);
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_outcomes_ndjson(&mut writer, outcomes).await.unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&writer.into_inner()),
+                "{\"seq\":3,\"type\":\"dispute\",\"client\":1,\"tx\":1,\"status\":\"rejected\",\"timestamp\":null,\"reference\":null,\
+                 \"merchant\":null,\"description\":null,\"metadata\":{\"region\":\"eu\"},\"rule\":null,\"reason\":\"Unknown transaction\"}\n"
+            );
+
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+            write_transactions_ndjson(&mut writer, vec![dispute.clone()]).await.unwrap();
+            let mut input = std::io::Cursor::new(writer.into_inner());
+            let replayed = read_ndjson(&mut input).await.collect::<Vec<_>>().await;
+            dispute.sequence = None;
+            assert_eq!(replayed.into_iter().map(|t| t.unwrap()).collect::<Vec<_>>(), vec![dispute]);
+        })
+    }
+}
@@ -0,0 +1,353 @@
+use std::{io::Cursor, panic::AssertUnwindSafe, sync::{mpsc, Arc, Mutex}};
+
+use futures::StreamExt;
+use models::transactions::Transaction;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{compression::decompress, dialect::Dialect, reader::{read_csv_with_dialect, ReadMode, Reader, ValidationError}};
+
+// ParallelOptions tunes read_csv_parallel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelOptions {
+    // Chunks are cut at the last record boundary within chunk_size bytes,
+    // a record larger than that makes its chunk larger.
+    pub chunk_size: usize,
+    // Number of parser threads, as many chunks are parsed at the same time.
+    pub threads: usize,
+}
+
+impl ParallelOptions {
+    pub fn new(threads: usize) -> Self {
+        Self { threads, ..Self::default() }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 4 << 20,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+// read_csv_parallel reads the same transactions as read_csv_with_dialect, but
+// splits the input into chunks parsed on a pool of threads. Transactions are
+// returned in input order, so the order of each client is kept, and errors
+// carry their position in the whole input.
+pub async fn read_csv_parallel(reader: &mut Reader, mode: ReadMode, dialect: Dialect, options: ParallelOptions) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
+    let mut splitter = Splitter::new(decompress(reader).await, dialect.quote as u8, options.chunk_size.max(1));
+    let header = match dialect.has_headers {
+        true => splitter.header().await,
+        false => Ok(Vec::new()),
+    };
+    // Each chunk is parsed with the header in front of it, check it once here.
+    let header_errors = match header {
+        Ok(ref header) => parse_chunk(header, Chunk::default(), mode, dialect.clone()),
+        Err(ref e) => vec![Err(anyhow::anyhow!("failed to read the header: {}", e))],
+    };
+    let header_failed = !header_errors.is_empty();
+    let header = Arc::new(header.unwrap_or_default());
+
+    let chunks = futures::stream::unfold(splitter, |mut splitter| async move {
+        match splitter.next_chunk().await {
+            Ok(chunk) => chunk.map(|chunk| (Ok(chunk), splitter)),
+            Err(e) => {
+                splitter.stop();
+                Some((Err(e), splitter))
+            },
+        }
+    });
+    let threads = options.threads.max(1);
+    let pool = Arc::new(ParserPool::new(threads));
+    // Records of the chunks before, to rebase error positions.
+    let mut records = 0;
+    let transactions = chunks
+        .take_while(move |_| futures::future::ready(!header_failed))
+        .map(move |chunk| {
+            let header = header.clone();
+            let dialect = dialect.clone();
+            let pool = pool.clone();
+            async move {
+                match chunk {
+                    Ok(chunk) => {
+                        let offset = (chunk.line_offset, chunk.byte_offset);
+                        (offset, pool.parse(header, chunk, mode, dialect).await)
+                    },
+                    Err(e) => ((0, 0), vec![Err(anyhow::Error::from(e))]),
+                }
+            }
+        })
+        .buffered(threads)
+        .flat_map(move |((line_offset, byte_offset), parsed)| {
+            let parsed = parsed.into_iter()
+                .map(|transaction| transaction.map_err(|e| relocate(e, records, line_offset, byte_offset)))
+                .collect::<Vec<_>>();
+            records += parsed.len() as u64;
+            futures::stream::iter(parsed)
+        });
+    futures::stream::iter(header_errors).chain(transactions)
+}
+
+// Chunk is a run of complete records along with the number of lines and bytes
+// of the input before it, less those of the header it is parsed with.
+#[derive(Debug, Default)]
+struct Chunk {
+    bytes: Vec<u8>,
+    line_offset: u64,
+    byte_offset: u64,
+}
+
+// Splitter cuts the input into chunks of complete records.
+struct Splitter<'a> {
+    input: Box<dyn AsyncRead + Send + Unpin + 'a>,
+    buffer: Vec<u8>,
+    quote: u8,
+    chunk_size: usize,
+    eof: bool,
+    // Lines and bytes consumed so far, and those of the header.
+    lines: u64,
+    header_lines: u64,
+    bytes: u64,
+    header_bytes: u64,
+}
+
+impl<'a> Splitter<'a> {
+    fn new(input: Box<dyn AsyncRead + Send + Unpin + 'a>, quote: u8, chunk_size: usize) -> Self {
+        Self { input, buffer: Vec::new(), quote, chunk_size, eof: false, lines: 0, header_lines: 0, bytes: 0, header_bytes: 0 }
+    }
+
+    async fn header(&mut self) -> std::io::Result<Vec<u8>> {
+        let header = self.take(|buffer, quote| boundaries(buffer, quote).next()).await?;
+        self.header_lines = self.lines;
+        self.header_bytes = self.bytes;
+        Ok(header)
+    }
+
+    async fn next_chunk(&mut self) -> std::io::Result<Option<Chunk>> {
+        let line_offset = self.lines - self.header_lines;
+        let byte_offset = self.bytes - self.header_bytes;
+        let bytes = self.take(|buffer, quote| boundaries(buffer, quote).last()).await?;
+        Ok((!bytes.is_empty()).then_some(Chunk { bytes, line_offset, byte_offset }))
+    }
+
+    // take reads at least chunk_size bytes, unless the input ends first, and
+    // returns them up to the record boundary picked by end, or all at the end of input.
+    async fn take(&mut self, end: impl Fn(&[u8], u8) -> Option<usize>) -> std::io::Result<Vec<u8>> {
+        let mut wanted = self.chunk_size;
+        loop {
+            while !self.eof && self.buffer.len() < wanted {
+                self.buffer.reserve(wanted - self.buffer.len());
+                if self.input.read_buf(&mut self.buffer).await? == 0 {
+                    self.eof = true;
+                }
+            }
+            let end = match end(&self.buffer, self.quote) {
+                Some(end) => end,
+                None if self.eof => self.buffer.len(),
+                // A single record is larger than a chunk, read on.
+                None => {
+                    wanted = self.buffer.len() + self.chunk_size;
+                    continue;
+                },
+            };
+            let rest = self.buffer.split_off(end);
+            let taken = std::mem::replace(&mut self.buffer, rest);
+            self.lines += taken.iter().filter(|b| **b == b'\n').count() as u64;
+            self.bytes += taken.len() as u64;
+            return Ok(taken);
+        }
+    }
+
+    fn stop(&mut self) {
+        self.eof = true;
+        self.buffer.clear();
+    }
+}
+
+// boundaries returns the offsets right after each newline outside of quotes,
+// where a record ends. Escaped quotes toggle twice, so they keep the state.
+fn boundaries(bytes: &[u8], quote: u8) -> impl Iterator<Item = usize> + '_ {
+    let mut quoted = false;
+    bytes.iter().enumerate().filter_map(move |(i, b)| {
+        if *b == quote {
+            quoted = !quoted;
+        }
+        (*b == b'\n' && !quoted).then_some(i + 1)
+    })
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// ParserPool parses chunks on a fixed set of threads, which stop once the pool is dropped.
+struct ParserPool {
+    jobs: mpsc::Sender<Job>,
+    // Why no thread could be started, if so.
+    spawn_error: Option<String>,
+}
+
+impl ParserPool {
+    fn new(threads: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut spawn_error = None;
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            let spawned = std::thread::Builder::new()
+                .name("csv-parser".to_string())
+                .spawn(move || loop {
+                    let job = match receiver.lock().expect("csv parser jobs lock poisoned").recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    // A panicking job drops its result sender, the thread keeps serving.
+                    let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+                });
+            if let Err(e) = spawned {
+                spawn_error = Some(e.to_string());
+            }
+        }
+        Self { jobs, spawn_error }
+    }
+
+    async fn parse(&self, header: Arc<Vec<u8>>, chunk: Chunk, mode: ReadMode, dialect: Dialect) -> Vec<Result<Transaction, anyhow::Error>> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = sender.send(parse_chunk(&header, chunk, mode, dialect));
+        });
+        // Sending fails when no thread could be started.
+        if self.jobs.send(job).is_err() {
+            return vec![Err(anyhow::anyhow!("failed to start csv parser threads: {}", self.spawn_error.as_deref().unwrap_or_default()))];
+        }
+        receiver.await.unwrap_or_else(|_| vec![Err(anyhow::anyhow!("csv parser thread panicked"))])
+    }
+}
+
+// parse_chunk parses the chunk behind the header with the sequential reader,
+// it runs on a parser thread so it blocks on the in memory reader.
+fn parse_chunk(header: &[u8], chunk: Chunk, mode: ReadMode, dialect: Dialect) -> Vec<Result<Transaction, anyhow::Error>> {
+    let mut bytes = Vec::with_capacity(header.len() + chunk.bytes.len());
+    bytes.extend_from_slice(header);
+    bytes.extend_from_slice(&chunk.bytes);
+    let mut reader = Cursor::new(bytes);
+    futures::executor::block_on(async {
+        read_csv_with_dialect(&mut reader, mode, dialect).await
+            .collect::<Vec<_>>()
+            .await
+    })
+}
+
+// relocate moves the position of an error in a chunk to its position in the input,
+// given the records, lines and bytes of the input before the chunk less those of the header.
+// Csv errors are formatted like csv_async formats them.
+fn relocate(mut e: anyhow::Error, records: u64, lines: u64, bytes: u64) -> anyhow::Error {
+    if let Some(validation) = e.downcast_mut::<ValidationError>() {
+        validation.line += lines;
+        return e;
+    }
+    let err = match e.downcast_ref::<csv_async::Error>() {
+        Some(err) => err,
+        None => return e,
+    };
+    let pos = match err.position() {
+        Some(pos) => pos,
+        None => return e,
+    };
+    let (record, line, byte) = (pos.record() + records, pos.line() + lines, pos.byte() + bytes);
+    match err.kind() {
+        csv_async::ErrorKind::Deserialize { err, .. } =>
+            anyhow::anyhow!("CSV deserialize error: record {} (line {}, byte: {}): {}", record, line, byte, err),
+        csv_async::ErrorKind::Utf8 { err, .. } =>
+            anyhow::anyhow!("CSV parse error: record {} (line {}, field: {}, byte: {}): {}", record, line, err.field(), byte, err),
+        csv_async::ErrorKind::UnequalLengths { expected_len, len, .. } =>
+            anyhow::anyhow!("CSV error: record {} (line: {}, byte: {}): found record with {} fields, but the previous record has {} fields", record, line, byte, len, expected_len),
+        _ => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use futures::StreamExt;
+    use models::logger::create_span;
+
+    use crate::{dialect::Dialect, reader::{read_csv_with_dialect, ReadMode}};
+    use super::{boundaries, read_csv_parallel, ParallelOptions};
+
+    fn input() -> String {
+        let mut input = "type,client,tx,amount,description\n".to_string();
+        for tx in 1..=200 {
+            match tx % 4 {
+                0 => input.push_str(&format!("withdrawal,{},{},1.5,\"multi\nline, \"\"quoted\"\"\"\n", tx % 7, tx)),
+                1 => input.push_str(&format!("deposit,{},{},10,\n", tx % 7, tx)),
+                2 => input.push_str(&format!("dispute,{},{},,\n", tx % 7, tx - 1)),
+                _ => input.push_str(&format!("deposito,{},{},1,\n", tx % 7, tx)),
+            }
+        }
+        input
+    }
+
+    #[test]
+    fn test_boundaries() {
+        assert_eq!(boundaries(b"a,b\n\"c\nd\",e\nf", b'"').collect::<Vec<_>>(), vec![4, 12]);
+        assert_eq!(boundaries(b"a,\"\"\"b\n\"\n", b'"').collect::<Vec<_>>(), vec![9]);
+        assert_eq!(boundaries(b"a,'b\n'\n", b'\'').collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn test_read_csv_parallel() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for mode in [ReadMode::Lenient, ReadMode::Strict] {
+                let mut reader = Cursor::new(input().into_bytes());
+                let expected = read_csv_with_dialect(&mut reader, mode, Dialect::default()).await
+                    .map(|t| t.map_err(|e| e.to_string()))
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(expected.len(), 200);
+                if mode == ReadMode::Lenient {
+                    assert!(expected[198].as_ref().unwrap_err().starts_with("CSV deserialize error: record 199 (line 249, byte: "));
+                }
+
+                for (threads, chunk_size) in [(1, 1), (4, 64), (3, 1000), (2, 1 << 20)] {
+                    let mut reader = Cursor::new(input().into_bytes());
+                    let options = ParallelOptions::new(threads).with_chunk_size(chunk_size);
+                    let transactions = read_csv_parallel(&mut reader, mode, Dialect::default(), options).await
+                        .map(|t| t.map_err(|e| e.to_string()))
+                        .collect::<Vec<_>>()
+                        .await;
+                    assert_eq!(transactions, expected);
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_read_csv_parallel_header() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mut reader = Cursor::new(b"type,client,amount\ndeposit,1,1.0\ndeposit,2,1.0\n".to_vec());
+            let options = ParallelOptions::new(2).with_chunk_size(8);
+            let errors = read_csv_parallel(&mut reader, ReadMode::Strict, Dialect::default(), options).await
+                .map(|t| t.unwrap_err().to_string())
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(errors, vec!["line 1: missing column tx"]);
+
+            let dialect = Dialect { delimiter: ';', has_headers: false, columns: vec!["type".into(), "client".into(), "tx".into(), "amount".into()], ..Dialect::default() };
+            let mut reader = Cursor::new(b"deposit;1;1;2.0\ndeposit;1;2;3.0".to_vec());
+            let options = ParallelOptions::new(2).with_chunk_size(4);
+            let ids = read_csv_parallel(&mut reader, ReadMode::Strict, dialect, options).await
+                .map(|t| t.unwrap().id.0)
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(ids, vec![1, 2]);
+        })
+    }
+}
@@ -1,22 +1,219 @@
-use models::transactions::Transaction;
+use std::fmt;
+
+use models::{ids::{ClientId, TransactionId}, transactions::{Transaction, TransactionKind}};
 use tokio_stream::StreamExt;
 
+use crate::{compression::decompress, dialect::Dialect};
+
 pub type Reader = dyn tokio::io::AsyncRead + Send + Sync + Unpin;
 
+// Columns mapped to Transaction fields, any other column is read as metadata.
+pub(crate) const TRANSACTION_COLUMNS: [&str; 8] = ["type", "client", "tx", "amount", "timestamp", "reference", "merchant", "description"];
+
+// Columns a strict header must contain.
+const REQUIRED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+// ReadMode selects how much of the input is validated while reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReadMode {
+    // Lenient accepts any record which deserializes, short rows included.
+    #[default]
+    Lenient,
+    // Strict checks the header, the field count of each row and the amount
+    // required by each transaction kind.
+    Strict,
+}
+
+// ValidationError locates an invalid record, line and column start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub line: u64,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub(crate) fn new(line: u64, column: Option<usize>, message: String) -> Self {
+        Self { line, column, message }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}: {}", self.line, column, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// HeaderError is an invalid strict header, no record of the input can be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderError(pub ValidationError);
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
 pub async fn read_csv(reader: &mut Reader) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
-    csv_async::AsyncReaderBuilder::new()
+    read_csv_with_mode(reader, ReadMode::Lenient).await
+}
+
+// read_csv_with_mode reads transactions validated according to mode. In strict
+// mode an invalid header is the only item of the stream, as a HeaderError.
+// Gzip and zstd input is decompressed on the fly.
+pub async fn read_csv_with_mode(reader: &mut Reader, mode: ReadMode) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
+    read_csv_with_dialect(reader, mode, Dialect::default()).await
+}
+
+// read_csv_with_dialect reads input laid out as described by dialect, its
+// columns are renamed to transaction fields before validation.
+pub async fn read_csv_with_dialect(reader: &mut Reader, mode: ReadMode, dialect: Dialect) -> impl futures::Stream<Item = Result<Transaction, anyhow::Error>> + '_ {
+    let mut rdr = csv_async::AsyncReaderBuilder::new()
         .flexible(true)
         .trim(csv_async::Trim::All)
-        .create_reader(reader)
-        .into_records()
-        .map(|record| {
-            record
-                .and_then(|r| {
-                    r.deserialize::<Transaction>(None)
-                        .map(std::convert::Into::into)
-                })
+        .delimiter(dialect.delimiter as u8)
+        .quote(dialect.quote as u8)
+        .has_headers(dialect.has_headers)
+        .create_reader(decompress(reader).await);
+    let read = match dialect.has_headers {
+        true => rdr.headers().await.cloned().unwrap_or_default(),
+        false => csv_async::StringRecord::new(),
+    };
+    let headers = dialect.headers(&read);
+    let header_error = match mode {
+        ReadMode::Strict => validate_headers(&headers).err(),
+        ReadMode::Lenient => None,
+    };
+    let header_failed = header_error.is_some();
+
+    let records = rdr.into_records()
+        .take_while(move |_| !header_failed)
+        .map(move |record| match mode {
+            ReadMode::Lenient => record
                 .map_err(anyhow::Error::from)
-        })
+                .and_then(|r| read_lenient_record(&headers, &r)),
+            ReadMode::Strict => record
+                .map_err(|e| located_error(&e, 0))
+                .and_then(|r| read_strict_record(&headers, &r))
+                .map_err(anyhow::Error::from),
+        });
+    tokio_stream::iter(header_error.map(|e| Err(anyhow::Error::from(HeaderError(e))))).chain(records)
+}
+
+fn read_record(headers: &csv_async::StringRecord, record: &csv_async::StringRecord) -> Result<Transaction, csv_async::Error> {
+    // Flexible rows may omit trailing columns, deserialize them against the matching headers only.
+    let mut transaction = if record.len() < headers.len() {
+        let row_headers = headers.iter().take(record.len()).collect::<csv_async::StringRecord>();
+        record.deserialize::<Transaction>(Some(&row_headers))?
+    } else {
+        record.deserialize::<Transaction>(Some(headers))?
+    };
+    transaction.metadata = read_metadata(headers, record);
+    Ok(transaction)
+}
+
+fn read_lenient_record(headers: &csv_async::StringRecord, record: &csv_async::StringRecord) -> Result<Transaction, anyhow::Error> {
+    let transaction = read_record(headers, record)?;
+    let line = record.position().map_or(0, |p| p.line());
+    check_transaction(&transaction, ReadMode::Lenient).map_err(|(field, message)| ValidationError::new(line, column(headers, field), message))?;
+    Ok(transaction)
+}
+
+fn read_strict_record(headers: &csv_async::StringRecord, record: &csv_async::StringRecord) -> Result<Transaction, ValidationError> {
+    let line = record.position().map_or(0, |p| p.line());
+    if record.len() != headers.len() {
+        return Err(ValidationError::new(line, None, format!("expected {} fields, found {}", headers.len(), record.len())));
+    }
+    check_field::<TransactionKind>(headers, record, "type", line)?;
+    check_field::<ClientId>(headers, record, "client", line)?;
+    check_field::<TransactionId>(headers, record, "tx", line)?;
+    check_field::<Option<f32>>(headers, record, "amount", line)?;
+    check_field::<Option<u64>>(headers, record, "timestamp", line)?;
+    let transaction = read_record(headers, record).map_err(|e| located_error(&e, line))?;
+    check_transaction(&transaction, ReadMode::Strict).map_err(|(field, message)| ValidationError::new(line, column(headers, field), message))?;
+    Ok(transaction)
+}
+
+// check_transaction validates a transaction read according to mode, failures
+// name the field at fault. Both modes reject admin operations, they skip rules
+// and fraud hooks so a client could release its own held transaction otherwise.
+// Strict mode also requires a positive amount for deposits and withdrawals, and
+// no amount for other kinds.
+pub(crate) fn check_transaction(transaction: &Transaction, mode: ReadMode) -> Result<(), (&'static str, String)> {
+    let kind = transaction.kind.name();
+    if transaction.kind.is_admin() {
+        return Err(("type", format!("{} is an admin operation and can not be read from input", kind)));
+    }
+    match (&transaction.kind, transaction.amount) {
+        (_, _) if mode == ReadMode::Lenient => Ok(()),
+        (TransactionKind::Deposit | TransactionKind::Withdrawal, None) =>
+            Err(("amount", format!("amount is required for {}", kind))),
+        (TransactionKind::Deposit | TransactionKind::Withdrawal, Some(amount)) if !(amount.is_finite() && amount > 0.0) =>
+            Err(("amount", format!("amount must be positive, found {}", amount))),
+        (TransactionKind::Deposit | TransactionKind::Withdrawal, Some(_)) => Ok(()),
+        (_, Some(_)) => Err(("amount", format!("amount is not allowed for {}", kind))),
+        (_, None) => Ok(()),
+    }
+}
+
+// column returns the column of the header name, starting at 1.
+fn column(headers: &csv_async::StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|h| h == name).map(|c| c + 1)
+}
+
+// check_field deserializes a single column so a failure points at it.
+fn check_field<T: serde::de::DeserializeOwned>(headers: &csv_async::StringRecord, record: &csv_async::StringRecord, name: &str, line: u64) -> Result<(), ValidationError> {
+    let column = match headers.iter().position(|h| h == name) {
+        Some(column) => column,
+        None => return Ok(()),
+    };
+    let field = csv_async::StringRecord::from(vec![record.get(column).unwrap_or_default()]);
+    field.deserialize::<T>(None)
+        .map(|_| ())
+        .map_err(|e| ValidationError::new(line, Some(column + 1), located_error(&e, line).message))
+}
+
+fn validate_headers(headers: &csv_async::StringRecord) -> Result<(), ValidationError> {
+    let line = headers.position().map_or(1, |p| p.line());
+    for (column, header) in headers.iter().enumerate() {
+        if header.is_empty() {
+            return Err(ValidationError::new(line, Some(column + 1), "empty column name".to_string()));
+        }
+        if headers.iter().take(column).any(|h| h == header) {
+            return Err(ValidationError::new(line, Some(column + 1), format!("duplicate column {}", header)));
+        }
+    }
+    match REQUIRED_COLUMNS.iter().find(|required| !headers.iter().any(|h| h == **required)) {
+        Some(missing) => Err(ValidationError::new(line, None, format!("missing column {}", missing))),
+        None => Ok(()),
+    }
+}
+
+// located_error converts a csv error, using line when it has no position of its own.
+fn located_error(err: &csv_async::Error, line: u64) -> ValidationError {
+    let line = err.position().map_or(line, |p| p.line());
+    match err.kind() {
+        csv_async::ErrorKind::Deserialize { err, .. } =>
+            ValidationError::new(line, err.field().map(|f| f as usize + 1), err.kind().to_string()),
+        csv_async::ErrorKind::Utf8 { err, .. } =>
+            ValidationError::new(line, Some(err.field() + 1), "invalid utf-8".to_string()),
+        _ => ValidationError::new(line, None, err.to_string()),
+    }
+}
+
+fn read_metadata(headers: &csv_async::StringRecord, record: &csv_async::StringRecord) -> std::collections::BTreeMap<String, String> {
+    headers.iter()
+        .zip(record.iter())
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty() && !TRANSACTION_COLUMNS.contains(key))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
 }
 
 #[cfg(test)]
@@ -25,9 +222,12 @@ mod tests {
     use std::fmt::Error;
     use futures::{FutureExt, TryStreamExt};
     use models::{logger::create_span, transactions::{Transaction, TransactionKind}};
+    use tokio::io::AsyncWriteExt;
     use tokio_stream::StreamExt;
 
-    use super::read_csv;
+    use crate::dialect::Dialect;
+
+    use super::{read_csv, read_csv_with_dialect, read_csv_with_mode, ReadMode};
 
 
     #[test]
@@ -51,7 +251,8 @@ mod tests {
         deposit,1,10,8.4521
         withdrawal,1,11,7.9462
         withdrawal,1,12,
-        deposit,1,12,"
+        deposit,1,12,
+        release,1,13,"
             .as_bytes();
 
         let result = read_csv(&mut input)
@@ -61,21 +262,253 @@ mod tests {
             .await;
 
         let expected = vec![
-            Ok(Transaction { kind: TransactionKind::Deposit, client_id: 1, id: 1, amount: Some(2.0), under_dispute: false }),
+            Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.0))),
             Err(Error),
-            Ok(Transaction { kind: TransactionKind::Withdrawal, client_id: 1, id: 3, amount: Some(5.0), under_dispute: false }),
-            Ok(Transaction { kind: TransactionKind::Resolve, client_id: 1, id: 4, amount: None, under_dispute: false }),
-            Ok(Transaction { kind: TransactionKind::Resolve, client_id: 1, id: 5, amount: Some(50.0), under_dispute: false }),
-            Ok(Transaction { kind: TransactionKind::Dispute, client_id: 1, id: 6, amount: None, under_dispute: false }),
-            Ok(Transaction { kind: TransactionKind::Dispute, client_id: 1, id: 7, amount: Some(50.0), under_dispute: false }),
-            Ok(Transaction { kind: TransactionKind::ChargeBack, client_id: 1, id: 8, amount: None, under_dispute: false }), 
-            Ok(Transaction { kind: TransactionKind::ChargeBack, client_id: 1, id: 9, amount: Some(100.0), under_dispute: false }), 
-            Ok(Transaction { kind: TransactionKind::Deposit, client_id: 1, id: 10, amount: Some(8.4521), under_dispute: false }), 
-            Ok(Transaction { kind: TransactionKind::Withdrawal, client_id: 1, id: 11, amount: Some(7.9462), under_dispute: false }), 
-            Ok(Transaction { kind: TransactionKind::Withdrawal, client_id: 1, id: 12, amount: None, under_dispute: false }), 
-            Ok(Transaction { kind: TransactionKind::Deposit, client_id: 1, id: 12, amount: None, under_dispute: false })
+            Ok(Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(5.0))),
+            Ok(Transaction::new(TransactionKind::Resolve, 1, 4, None)),
+            Ok(Transaction::new(TransactionKind::Resolve, 1, 5, Some(50.0))),
+            Ok(Transaction::new(TransactionKind::Dispute, 1, 6, None)),
+            Ok(Transaction::new(TransactionKind::Dispute, 1, 7, Some(50.0))),
+            Ok(Transaction::new(TransactionKind::ChargeBack, 1, 8, None)), 
+            Ok(Transaction::new(TransactionKind::ChargeBack, 1, 9, Some(100.0))), 
+            Ok(Transaction::new(TransactionKind::Deposit, 1, 10, Some(8.4521))), 
+            Ok(Transaction::new(TransactionKind::Withdrawal, 1, 11, Some(7.9462))), 
+            Ok(Transaction::new(TransactionKind::Withdrawal, 1, 12, None)),
+            Ok(Transaction::new(TransactionKind::Deposit, 1, 12, None)),
+            Err(Error),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[test]
+    fn test_read_csv_with_timestamp() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_read_csv_with_timestamp_test())
+    }
+
+    async fn run_read_csv_with_timestamp_test() {
+        let mut input = r"
+        type,client,tx,amount,timestamp
+        deposit,1,1,2,1000
+        dispute,1,1,,1010
+        resolve,1,1,"
+            .as_bytes();
+
+        let result = read_csv(&mut input)
+            .map(|tx| tx.map_err(|_| Error))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.0)).with_timestamp(1000)),
+            Ok(Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1010)),
+            Ok(Transaction::new(TransactionKind::Resolve, 1, 1, None)),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[test]
+    fn test_read_csv_with_metadata() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_read_csv_with_metadata_test())
+    }
+
+    async fn run_read_csv_with_metadata_test() {
+        let mut input = r"
+        type,client,tx,amount,timestamp,reference,merchant,description,channel,region
+        deposit,1,1,2,1000,ref-1,acme,top up,web,eu
+        withdrawal,1,2,1,,,,,atm,
+        dispute,1,1"
+            .as_bytes();
+
+        let result = read_csv(&mut input)
+            .map(|tx| tx.map_err(|_| Error))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut deposit = Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.0))
+            .with_timestamp(1000)
+            .with_metadata("channel", "web")
+            .with_metadata("region", "eu");
+        deposit.reference = Some("ref-1".to_string());
+        deposit.merchant = Some("acme".to_string());
+        deposit.description = Some("top up".to_string());
+
+        let expected = vec![
+            Ok(deposit),
+            Ok(Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(1.0)).with_metadata("channel", "atm")),
+            Ok(Transaction::new(TransactionKind::Dispute, 1, 1, None)),
         ];
 
         assert_eq!(result, expected)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_csv_id_range() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_read_csv_id_range_test())
+    }
+
+    async fn run_read_csv_id_range_test() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,70000,5000000000,1.0
+        deposit,4294967296,1,1.0
+        deposit,1,-1,1.0
+        deposit,1,18446744073709551616,1.0"
+            .as_bytes();
+
+        let result = read_csv(&mut input)
+            .map(|tx| tx.map_err(|e| e.to_string()))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result[0], Ok(Transaction::new(TransactionKind::Deposit, 70000, 5000000000, Some(1.0))));
+        assert!(result[1].as_ref().unwrap_err().contains("client id 4294967296 is out of range"));
+        assert!(result[2].as_ref().unwrap_err().contains("transaction id -1 is out of range"));
+        assert!(result[3].as_ref().unwrap_err().contains("transaction id"));
+        assert!(result[3].as_ref().unwrap_err().contains("is out of range"));
+    }
+
+    #[test]
+    fn test_read_csv_strict() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_read_csv_strict_test())
+    }
+
+    async fn run_read_csv_strict_test() {
+        let mut input = r"type,client,tx,amount
+        deposit,1,1,2
+        deposito,1,2,2.0
+        withdrawal,1,3,5.0
+        resolve,1,4,
+        resolve,1,5, 50.0
+        deposit,1,6,-1.0
+        withdrawal,1,7,
+        deposit,x,8,1.0
+        dispute,1,1
+        chargeback,1,1,
+        cancel,1,1,"
+            .as_bytes();
+
+        let result = read_csv_with_mode(&mut input, ReadMode::Strict)
+            .map(|tx| tx.map_err(|e| e.to_string()))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result[0], Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.0))));
+        assert!(result[1].as_ref().unwrap_err().starts_with("line 3, column 1: "));
+        assert_eq!(result[2], Ok(Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(5.0))));
+        assert_eq!(result[3], Ok(Transaction::new(TransactionKind::Resolve, 1, 4, None)));
+        assert_eq!(result[4], Err("line 6, column 4: amount is not allowed for resolve".to_string()));
+        assert_eq!(result[5], Err("line 7, column 4: amount must be positive, found -1".to_string()));
+        assert_eq!(result[6], Err("line 8, column 4: amount is required for withdrawal".to_string()));
+        assert!(result[7].as_ref().unwrap_err().starts_with("line 9, column 2: "));
+        assert_eq!(result[8], Err("line 10: expected 4 fields, found 3".to_string()));
+        assert_eq!(result[9], Ok(Transaction::new(TransactionKind::ChargeBack, 1, 1, None)));
+        assert_eq!(result[10], Err("line 12, column 1: cancel is an admin operation and can not be read from input".to_string()));
+    }
+
+    #[test]
+    fn test_read_csv_admin_operations() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for mode in [ReadMode::Lenient, ReadMode::Strict] {
+                let mut input = "client,tx,type,amount\n1,1,deposit,10.0\n1,1,release,\n".as_bytes();
+                let result = read_csv_with_mode(&mut input, mode)
+                    .map(|tx| tx.map_err(|e| e.to_string()))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(result, vec![
+                    Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))),
+                    Err("line 3, column 3: release is an admin operation and can not be read from input".to_string()),
+                ]);
+            }
+        })
+    }
+
+    #[test]
+    fn test_read_csv_strict_header() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            for (mut input, expected) in [
+                ("type,client,tx\ndeposit,1,1\n".as_bytes(), "line 1: missing column amount"),
+                ("type,client,tx,amount,tx\ndeposit,1,1,1.0,1\n".as_bytes(), "line 1, column 5: duplicate column tx"),
+            ] {
+                let result = read_csv_with_mode(&mut input, ReadMode::Strict)
+                    .map(|tx| tx.map_err(|e| e.to_string()))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(result, vec![Err(expected.to_string())]);
+            }
+        })
+    }
+
+    #[test]
+    fn test_read_compressed_csv() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let csv = "type,client,tx,amount\ndeposit,1,1,2.5\nwithdrawal,1,2,1.0\n";
+            let mut gzip = async_compression::tokio::write::GzipEncoder::new(Vec::new());
+            let mut zstd = async_compression::tokio::write::ZstdEncoder::new(Vec::new());
+            for encoder in [&mut gzip as &mut (dyn tokio::io::AsyncWrite + Unpin), &mut zstd] {
+                encoder.write_all(csv.as_bytes()).await.unwrap();
+                encoder.shutdown().await.unwrap();
+            }
+
+            for compressed in [gzip.into_inner(), zstd.into_inner()] {
+                let mut input = std::io::Cursor::new(compressed);
+                let result = read_csv(&mut input)
+                    .map(|tx| tx.map_err(|_| Error))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(result, vec![
+                    Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.5))),
+                    Ok(Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(1.0))),
+                ]);
+            }
+        })
+    }
+
+    #[test]
+    fn test_read_csv_with_dialect() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let mapping = [("transaction_id", "tx"), ("customer", "client")].into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<std::collections::BTreeMap<_, _>>();
+            let with_headers = Dialect { delimiter: ';', quote: '\'', mapping: mapping.clone(), ..Dialect::default() };
+            let headerless = Dialect {
+                has_headers: false,
+                columns: vec!["transaction_id".to_string(), "customer".to_string(), "type".to_string(), "amount".to_string(), "note".to_string()],
+                mapping,
+                ..Dialect::default()
+            };
+
+            for (input, dialect) in [
+                ("type;customer;transaction_id;amount;note\ndeposit;1;1;2.5;'a;b'\ndispute;1;1;;\n", with_headers),
+                ("1,1,deposit,2.5,a;b\n1,1,dispute,,\n", headerless),
+            ] {
+                let mut input = input.as_bytes();
+                let result = read_csv_with_dialect(&mut input, ReadMode::Strict, dialect)
+                    .map(|tx| tx.map_err(|e| e.to_string()))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(result, vec![
+                    Ok(Transaction::new(TransactionKind::Deposit, 1, 1, Some(2.5)).with_metadata("note", "a;b")),
+                    Ok(Transaction::new(TransactionKind::Dispute, 1, 1, None)),
+                ]);
+            }
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use futures::StreamExt;
-use models::{account::Account, error::Error};
+use models::{account::Account, ids::{ClientId, TransactionId}, outcome::{Outcome, OutcomeStatus}, stats::ExtendedAccount, transactions::{Transaction, TransactionKind}, error::Error};
+use serde::Serialize;
 
 pub type Writer = dyn tokio::io::AsyncWrite + Send + Sync + Unpin;
 
@@ -14,14 +15,129 @@ pub async fn write_csv(writer: &mut Writer, mut account_stream: impl futures::St
     Ok(())
 }
 
+// ExtendedRow is an account report row followed by the activity statistics of the client.
+#[derive(Debug, Serialize)]
+pub(crate) struct ExtendedRow {
+    client: ClientId,
+    available: f32,
+    held: f32,
+    total: f32,
+    locked: bool,
+    deposits: u64,
+    deposited: f32,
+    withdrawals: u64,
+    withdrawn: f32,
+    open_disputes: u64,
+    disputed: f32,
+    chargebacks: u64,
+    charged_back: f32,
+    rejected: u64,
+    rejected_amount: f32,
+}
+
+impl From<ExtendedAccount> for ExtendedRow {
+    fn from(extended: ExtendedAccount) -> Self {
+        let ExtendedAccount { mut account, mut stats } = extended;
+        account.to_max_display_precision();
+        stats.to_max_display_precision();
+        Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+            deposits: stats.deposits,
+            deposited: stats.deposited,
+            withdrawals: stats.withdrawals,
+            withdrawn: stats.withdrawn,
+            open_disputes: stats.open_disputes,
+            disputed: stats.disputed,
+            chargebacks: stats.chargebacks,
+            charged_back: stats.charged_back,
+            rejected: stats.rejected,
+            rejected_amount: stats.rejected_amount,
+        }
+    }
+}
+
+// OutcomeRow is an outcome with its metadata encoded in a single column.
+#[derive(Debug, Serialize)]
+struct OutcomeRow {
+    seq: Option<u64>,
+    #[serde(rename = "type")]
+    kind: TransactionKind,
+    client: ClientId,
+    tx: TransactionId,
+    status: OutcomeStatus,
+    timestamp: Option<u64>,
+    reference: Option<String>,
+    merchant: Option<String>,
+    description: Option<String>,
+    metadata: Option<String>,
+    rule: Option<String>,
+    reason: Option<String>,
+}
+
+impl From<Outcome> for OutcomeRow {
+    fn from(outcome: Outcome) -> Self {
+        let metadata = outcome.metadata_string();
+        Self {
+            seq: outcome.sequence,
+            kind: outcome.kind,
+            client: outcome.client,
+            tx: outcome.tx,
+            status: outcome.status,
+            timestamp: outcome.timestamp,
+            reference: outcome.reference,
+            merchant: outcome.merchant,
+            description: outcome.description,
+            metadata,
+            rule: outcome.rule,
+            reason: outcome.reason,
+        }
+    }
+}
+
+// write_extended_csv writes the account report with the activity statistics of each client.
+pub async fn write_extended_csv(writer: &mut Writer, mut extended_stream: impl futures::Stream<Item = ExtendedAccount> + Send + Unpin) -> Result<(), Error> {
+    let mut writer = csv_async::AsyncSerializer::from_writer(writer);
+
+    while let Some(extended) = extended_stream.next().await {
+        writer.serialize(ExtendedRow::from(extended)).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn write_outcomes_csv(writer: &mut Writer, mut outcome_stream: impl futures::Stream<Item = Outcome> + Send + Unpin) -> Result<(), Error> {
+    let mut writer = csv_async::AsyncSerializer::from_writer(writer);
+
+    while let Some(outcome) = outcome_stream.next().await {
+        writer.serialize(OutcomeRow::from(outcome)).await?;
+    }
+
+    Ok(())
+}
+
+// write_transactions_csv writes transactions in the input format, e.g. dead letters to replay.
+pub async fn write_transactions_csv(writer: &mut Writer, transactions: Vec<Transaction>) -> Result<(), Error> {
+    let mut writer = csv_async::AsyncSerializer::from_writer(writer);
+
+    for transaction in transactions {
+        writer.serialize(transaction).await?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use models::{logger::create_span, account::Account};
+    use models::{logger::create_span, account::Account, ids::ClientId, outcome::{Outcome, OutcomeStatus}, stats::{AccountStats, ExtendedAccount}, transactions::{Transaction, TransactionKind}};
     use tokio::io::BufWriter;
 
-    use crate::writer::write_csv;
+    use crate::writer::{write_csv, write_extended_csv, write_outcomes_csv, write_transactions_csv};
 
     
     
@@ -52,4 +168,87 @@ mod tests {
             "client,available,held,total,locked\n1,5.36,1.58,6.94,false\n2,8.19,3.08,11.27,true\n"
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_extended_csv() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let stats = AccountStats { deposits: 2, deposited: 6.94, withdrawals: 0, open_disputes: 1, disputed: 1.58, rejected: 1, rejected_amount: 100.0, ..AccountStats::new(ClientId(1)) };
+            let extended = vec![
+                ExtendedAccount { account: Account::load(1, 5.36, 1.58, false), stats },
+                ExtendedAccount { account: Account::load(2, 0.0, 0.0, true), stats: AccountStats::new(ClientId(2)) },
+            ];
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+
+            write_extended_csv(&mut writer, futures::stream::iter(extended)).await.unwrap();
+
+            let buffer = writer.into_inner();
+            assert_eq!(
+                String::from_utf8_lossy(&buffer),
+                "client,available,held,total,locked,deposits,deposited,withdrawals,withdrawn,open_disputes,disputed,chargebacks,charged_back,rejected,rejected_amount\n\
+                 1,5.36,1.58,6.94,false,2,6.94,0,0.0,1,1.58,0,0.0,1,100.0\n\
+                 2,0.0,0.0,0.0,true,0,0.0,0,0.0,0,0.0,0,0.0,0,0.0\n"
+            );
+        })
+    }
+
+    #[test]
+    fn test_write_outcomes_csv() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_write_outcomes_csv_test())
+    }
+
+    async fn run_write_outcomes_csv_test() {
+        let mut deposit = Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))
+            .with_timestamp(1000)
+            .with_metadata("region", "eu")
+            .with_metadata("channel", "web")
+            .with_metadata("note", "a=b;c\\d");
+        deposit.reference = Some("ref-1".to_string());
+        deposit.sequence = Some(0);
+
+        let input = vec![
+            Outcome::new(&deposit, OutcomeStatus::Applied, None),
+            Outcome::new(&Transaction::new(TransactionKind::ChargeBack, 1, 1, None), OutcomeStatus::AutoChargedBack, Some("Dispute deadline exceeded".to_string())),
+        ];
+
+        let outcome_stream = futures::stream::iter(input);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result = write_outcomes_csv(&mut writer, outcome_stream).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            csv,
+            "seq,type,client,tx,status,timestamp,reference,merchant,description,metadata,rule,reason\n\
+             0,deposit,1,1,applied,1000,ref-1,,,channel=web;note=a\\=b\\;c\\\\d;region=eu,,\n\
+             ,chargeback,1,1,auto_charged_back,,,,,,,Dispute deadline exceeded\n"
+        );
+    }
+
+    #[test]
+    fn test_write_transactions_csv() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(run_write_transactions_csv_test())
+    }
+
+    async fn run_write_transactions_csv_test() {
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let transactions = vec![
+            Transaction::new(TransactionKind::Deposit, 1, 2, None),
+            Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(1.5)).with_timestamp(1000),
+        ];
+
+        write_transactions_csv(&mut writer, transactions).await.unwrap();
+
+        let buffer = writer.into_inner();
+        assert_eq!(
+            String::from_utf8_lossy(&buffer),
+            "type,client,tx,amount,timestamp,reference,merchant,description\ndeposit,1,2,,,,,\nwithdrawal,1,3,1.5,1000,,,\n"
+        );
+    }
+}
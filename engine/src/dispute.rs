@@ -0,0 +1,73 @@
+use models::transactions::Transaction;
+
+// ExpiryAction is applied by the engine to disputes left open past the deadline.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ExpiryAction {
+    #[default]
+    Resolve,
+    ChargeBack,
+}
+
+// DisputePolicy controls how long deposits can be disputed and how long
+// disputes can stay open. All durations are in seconds and only apply to
+// transactions carrying a timestamp.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisputePolicy {
+    pub open_window: Option<u64>,
+    pub deadline: Option<u64>,
+    pub on_expiry: ExpiryAction,
+}
+
+impl DisputePolicy {
+    pub fn new(open_window: Option<u64>, deadline: Option<u64>, on_expiry: ExpiryAction) -> Self {
+        Self { open_window, deadline, on_expiry }
+    }
+
+    // Returns false when the dispute is raised after the open window of the referenced deposit.
+    pub fn is_within_window(&self, deposit: &Transaction, dispute: &Transaction) -> bool {
+        match (self.open_window, deposit.timestamp, dispute.timestamp) {
+            (Some(window), Some(deposited_at), Some(disputed_at)) => {
+                disputed_at.saturating_sub(deposited_at) <= window
+            },
+            _ => true,
+        }
+    }
+
+    // Returns true when a dispute opened at disputed_at has exceeded the deadline at now.
+    pub fn is_expired(&self, disputed_at: u64, now: u64) -> bool {
+        match self.deadline {
+            Some(deadline) => now.saturating_sub(disputed_at) > deadline,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::transactions::{Transaction, TransactionKind};
+
+    use super::{DisputePolicy, ExpiryAction};
+
+    #[test]
+    fn test_is_within_window() {
+        let policy = DisputePolicy::new(Some(100), None, ExpiryAction::Resolve);
+        let deposit = Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000);
+
+        let dispute = Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1100);
+        assert!(policy.is_within_window(&deposit, &dispute));
+
+        let dispute = Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1101);
+        assert!(!policy.is_within_window(&deposit, &dispute));
+
+        let dispute = Transaction::new(TransactionKind::Dispute, 1, 1, None);
+        assert!(policy.is_within_window(&deposit, &dispute));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let policy = DisputePolicy::new(None, Some(50), ExpiryAction::ChargeBack);
+        assert!(!policy.is_expired(1000, 1050));
+        assert!(policy.is_expired(1000, 1051));
+        assert!(!DisputePolicy::default().is_expired(0, u64::MAX));
+    }
+}
@@ -1,18 +1,129 @@
-use models::{transactions::{Transaction, TransactionKind}, error::{Error, ErrorKind}, account::Account, store::Store, infra::SpannedRuntime};
-use std::{sync::Arc, pin::Pin};
+use models::{transactions::{Transaction, TransactionKind}, ids::{ClientId, TransactionId}, error::{Error, ErrorKind}, account::{Account, AccountChange}, outcome::{Outcome, OutcomeStatus}, stats::{AccountStats, Activity, ActivityKind}, store::Store, infra::SpannedRuntime};
+use std::{collections::HashMap, sync::Arc, pin::Pin};
 
-use tokio::sync::mpsc::Receiver;
+use futures::StreamExt;
+
+use tokio::sync::{mpsc::{Receiver, UnboundedSender}, Mutex, OwnedMutexGuard};
+
+use crate::{dispute::{DisputePolicy, ExpiryAction}, fraud::{FraudHooks, Verdict}, middleware::{AmountValidation, LockCheck, Middleware}, rules::RuleSet};
+
+// Applied tells how a transaction accepted by apply_transaction was applied.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Applied {
+    Completed,
+    // Held by the named fraud hook, funds are reserved until released or cancelled.
+    Held(String, String),
+}
+
+// Processed is the result of processing a single transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Processed {
+    pub outcome: Outcome,
+    pub account: Account,
+}
+
+// Snapshot is the state a transaction can change, taken before it is applied:
+// the account of its client and the stored and held transactions with its id.
+// Restoring it rolls back a transaction which failed half way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    account: Account,
+    id: TransactionId,
+    stored: Option<Transaction>,
+    held: Option<Transaction>,
+}
+
+// ClientLocks serializes the processing of each client across clones of an
+// engine, so concurrent process calls never interleave on one account.
+#[derive(Clone, Default)]
+struct ClientLocks(Arc<std::sync::Mutex<HashMap<ClientId, Arc<Mutex<()>>>>>);
+
+impl ClientLocks {
+    async fn lock(&self, client_id: ClientId) -> ClientGuard {
+        let lock = self.0.lock().unwrap().entry(client_id).or_default().clone();
+        ClientGuard { locks: self.clone(), client_id, _guard: Some(lock.lock_owned().await) }
+    }
+}
+
+// ClientGuard holds the lock of a client, the lock is dropped from the map
+// once nobody holds or waits for it.
+struct ClientGuard {
+    locks: ClientLocks,
+    client_id: ClientId,
+    _guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let mut locks = self.locks.0.lock().unwrap();
+        if locks.get(&self.client_id).is_some_and(|lock| Arc::strong_count(lock) == 2) {
+            locks.remove(&self.client_id);
+        }
+    }
+}
 
 #[derive(Clone)]
 
 pub struct Engine<S: Store> {
     store: S,
+    dispute_policy: DisputePolicy,
+    rules: Arc<RuleSet>,
+    fraud_hooks: Arc<FraudHooks>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    changes: Option<UnboundedSender<AccountChange>>,
+    locks: ClientLocks,
+    snapshots: Option<Arc<std::sync::Mutex<Option<Snapshot>>>>,
 }
 
 impl <S: Store> Engine<S> 
 where S: 'static+Send+Clone{
     pub fn new(store: S) -> Self {
-        Engine{store}
+        Engine{
+            store,
+            dispute_policy: DisputePolicy::default(),
+            rules: Arc::new(RuleSet::default()),
+            fraud_hooks: Arc::new(FraudHooks::default()),
+            middlewares: vec![Arc::new(AmountValidation), Arc::new(LockCheck)],
+            changes: None,
+            locks: ClientLocks::default(),
+            snapshots: None,
+        }
+    }
+
+    pub fn with_dispute_policy(mut self, dispute_policy: DisputePolicy) -> Self {
+        self.dispute_policy = dispute_policy;
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Arc<RuleSet>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_fraud_hooks(mut self, fraud_hooks: Arc<FraudHooks>) -> Self {
+        self.fraud_hooks = fraud_hooks;
+        self
+    }
+
+    // with_middleware appends a middleware to the chain, after the built-in
+    // amount validation and lock check.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    // with_change_feed sends every account change to changes as it happens.
+    // The feed is unbounded so a slow reader never stalls processing.
+    pub fn with_change_feed(mut self, changes: UnboundedSender<AccountChange>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    // with_snapshots keeps the snapshot of the transaction being processed in
+    // snapshots, so a supervisor can restore it when the engine panics.
+    pub fn with_snapshots(mut self, snapshots: Arc<std::sync::Mutex<Option<Snapshot>>>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
     }
 
     pub async fn start(&self, rt: Arc<SpannedRuntime>, rx : Receiver<Transaction>) -> tokio::task::JoinHandle<()> {
@@ -24,70 +135,396 @@ where S: 'static+Send+Clone{
         self.store.get_all_accounts().await
     }
 
+    pub async fn outcomes(&self) -> Result<Pin<Box<dyn futures::Stream<Item = Outcome> + Send>>, Error> {
+        self.store.get_all_outcomes().await
+    }
+
+    pub async fn stats(&self) -> Result<Pin<Box<dyn futures::Stream<Item = AccountStats> + Send>>, Error> {
+        self.store.get_all_stats().await
+    }
+
     async fn process_txn(&self, mut rx : Receiver<Transaction>) -> Result<(), Error> {
         while let Some(transaction) = rx.recv().await {
-            tracing::info!("Payment engine processing transaction with id {}", transaction.id);
-            if !transaction.is_valid_amount() {
-                tracing::error!("Transaction with id {} has negative amount", transaction.id);
+            self.process(&transaction).await;
+        }
+        Ok(())
+    }
+
+    // process_blocking is process for callers outside of an async context, it
+    // runs on its own executor. Called from within a tokio runtime it would block
+    // a runtime thread, use process there instead.
+    pub fn process_blocking(&self, transaction: &Transaction) -> Result<Processed, Error> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(Error::new(ErrorKind::EngineError("process_blocking called from an async context, use process".to_string())));
+        }
+        Ok(futures::executor::block_on(self.process(transaction)))
+    }
+
+    // process applies a single transaction and returns its outcome along with
+    // the account of the client afterwards. The outcome is also recorded in the store.
+    // Calls for the same client on clones of the engine are processed one at a time.
+    pub async fn process(&self, transaction: &Transaction) -> Processed {
+        let _client = self.locks.lock(transaction.client_id).await;
+        tracing::info!("Payment engine processing transaction with id {}", transaction.id);
+        if let Some(now) = transaction.timestamp {
+            if let Err(e) = self.expire_disputes(transaction.client_id, now, transaction.sequence).await {
+                tracing::error!("Failed to expire disputes for client id {}: {}", transaction.client_id, e);
+            }
+        }
+
+        let mut account = match self.pre_apply(transaction).await {
+            Ok(account) => account,
+            Err(e) => return self.reject(transaction, &e).await,
+        };
+
+        let snapshot = self.snapshot(transaction, &account).await;
+        if let Some(snapshots) = &self.snapshots {
+            *snapshots.lock().unwrap() = Some(snapshot.clone());
+        }
+
+        if let Err(e) = self.store.add_transaction(transaction.clone()).await {
+            tracing::error!("Failed to add transaction with id {}",transaction.id);
+            return self.reject(transaction, &e).await;
+        }
+
+        let before = account.clone();
+        let transaction_result: Result<Applied, Error> = async {
+            let applied = self.apply_transaction(&mut account, transaction).await?;
+
+            self.store.update_account(&account).await?;
+            Ok(applied)
+        }.await;
+
+        match transaction_result {
+            Ok(applied) => {
+                for middleware in self.middlewares.iter() {
+                    middleware.post_apply(&account, transaction, &applied).await;
+                }
+                let outcome = match applied {
+                    Applied::Completed => {
+                        match (&transaction.kind, &snapshot.held) {
+                            // A release completes the held deposit or withdrawal, it counts as one.
+                            (TransactionKind::Release, Some(held)) => self.record_activity(held, true).await,
+                            _ => self.record_activity(transaction, before != account).await,
+                        }
+                        Outcome::new(transaction, OutcomeStatus::Applied, None)
+                    },
+                    Applied::Held(hook, reason) => {
+                        let mut outcome = Outcome::new(transaction, OutcomeStatus::Held, Some(reason));
+                        outcome.rule = Some(hook);
+                        outcome
+                    },
+                };
+                self.add_outcome(outcome.clone()).await;
+                self.send_change(transaction, &account);
+                Processed { outcome, account }
+            },
+            Err(e) => {
+                tracing::warn!("Rolling back transaction for tx {}", transaction.id);
+                if let Err(err) = self.restore(&snapshot).await {
+                    tracing::error!("Failed to rollback transaction {}: {}", transaction.id, err);
+                }
+                self.reject(transaction, &e).await
+            }
+        }
+    }
+
+    async fn snapshot(&self, transaction: &Transaction, account: &Account) -> Snapshot {
+        Snapshot {
+            account: account.clone(),
+            id: transaction.id,
+            stored: self.store.get_transaction(transaction.id).await.ok(),
+            held: self.store.get_held_transaction(transaction.id).await.ok(),
+        }
+    }
+
+    // restore puts the account and the transactions of the snapshot back in the store.
+    pub async fn restore(&self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.store.remove_held_transaction(snapshot.id).await?;
+        self.store.delete_transaction(snapshot.id).await?;
+        if let Some(stored) = &snapshot.stored {
+            self.store.add_transaction(stored.clone()).await?;
+        }
+        if let Some(held) = &snapshot.held {
+            self.store.hold_transaction(held.clone()).await?;
+        }
+        // Accounts are only written when changed, so clients without any
+        // applied transaction stay out of the report.
+        if self.store.get_account(snapshot.account.client).await? != snapshot.account {
+            self.store.update_account(&snapshot.account).await?;
+        }
+        Ok(())
+    }
+
+    // pre_apply loads the client account and runs the pre apply middlewares,
+    // the first failing middleware rejects the transaction.
+    async fn pre_apply(&self, transaction: &Transaction) -> Result<Account, Error> {
+        let account = self.store.get_account(transaction.client_id).await?;
+        for middleware in self.middlewares.iter() {
+            if let Err(e) = middleware.pre_apply(&account, transaction).await {
+                tracing::debug!("Middleware {} rejected transaction {}", middleware.name(), transaction.id);
+                return Err(e);
+            }
+        }
+        Ok(account)
+    }
+
+    // reject records the rejection and returns it with the stored account,
+    // which is left untouched by the rejected transaction.
+    async fn reject(&self, transaction: &Transaction, err: &Error) -> Processed {
+        for middleware in self.middlewares.iter() {
+            middleware.on_reject(transaction, err).await;
+        }
+        let outcome = self.record_rejection(transaction, err).await;
+        self.add_activity(Activity::new(transaction.client_id, ActivityKind::Rejected, transaction.amount.unwrap_or_default())).await;
+        let account = match self.store.get_account(transaction.client_id).await {
+            Ok(account) => account,
+            Err(e) => {
+                tracing::error!("Failed to load account for client id {}: {}", transaction.client_id, e);
+                Account::new(transaction.client_id.0)
+            },
+        };
+        Processed { outcome, account }
+    }
+
+    // expire_all_disputes applies the dispute policy expiry action to the open
+    // disputes of all clients which have exceeded their deadline at now, e.g.
+    // the latest timestamp of the input once it is fully processed. Clients
+    // without later transactions would keep their disputes open otherwise.
+    pub async fn expire_all_disputes(&self, now: u64) -> Result<(), Error> {
+        let clients = self.store.get_all_accounts().await?.map(|account| account.client).collect::<Vec<_>>().await;
+        for client_id in clients {
+            let _client = self.locks.lock(client_id).await;
+            self.expire_disputes(client_id, now, None).await?;
+        }
+        Ok(())
+    }
+
+    // expire_disputes applies the dispute policy expiry action to every open
+    // dispute of the client which has exceeded its deadline at now. Expiries
+    // carry the sequence of the transaction which triggered them.
+    async fn expire_disputes(&self, client_id: ClientId, now: u64, sequence: Option<u64>) -> Result<(), Error> {
+        if self.dispute_policy.deadline.is_none() {
+            return Ok(());
+        }
+
+        for disputed in self.store.get_disputed_transactions(client_id).await? {
+            let disputed_at = match disputed.disputed_at {
+                Some(disputed_at) => disputed_at,
+                None => continue,
+            };
+            if !self.dispute_policy.is_expired(disputed_at, now) {
                 continue;
             }
 
-            if let Err(_) = self.store.add_transaction(transaction.clone()).await {
-                tracing::error!("Failed to add transaction with id {}",transaction.id);
+            let mut account = self.store.get_account(client_id).await?;
+            if account.locked {
+                tracing::error!("Account locked for client id {}, skipping expired dispute on tx {}", client_id, disputed.id);
                 continue;
             }
 
-            let transaction_result: Result<(), Error> = async {
-                let mut account = self.store.get_account(transaction.client_id).await?;
+            let (kind, status) = match self.dispute_policy.on_expiry {
+                ExpiryAction::Resolve => (TransactionKind::Resolve, OutcomeStatus::AutoResolved),
+                ExpiryAction::ChargeBack => (TransactionKind::ChargeBack, OutcomeStatus::AutoChargedBack),
+            };
+            // Expiry carries the references of the disputed deposit to the outcome.
+            let expiry = Transaction {
+                timestamp: Some(now),
+                reference: disputed.reference.clone(),
+                merchant: disputed.merchant.clone(),
+                description: disputed.description.clone(),
+                metadata: disputed.metadata.clone(),
+                sequence,
+                ..Transaction::new(kind, client_id.0, disputed.id.0, None)
+            };
+            tracing::warn!("Dispute on tx {} exceeded its deadline, applying {:?}", disputed.id, expiry.kind);
+
+            // Expiries are not client transactions, rules neither evaluate nor count them.
+            match self.execute(&mut account, &expiry).await {
+                Ok(_) => {
+                    self.store.update_account(&account).await?;
+                    self.record_outcome(&expiry, status, Some("Dispute deadline exceeded".to_string())).await;
+                    self.record_activity(&expiry, true).await;
+                    self.send_change(&expiry, &account);
+                },
+                Err(e) => {
+                    tracing::error!("Failed to apply expiry for tx {}: {}", disputed.id, e);
+                    self.record_rejection(&expiry, &e).await;
+                },
+            }
+        }
+        Ok(())
+    }
 
-                if account.locked {
-                    tracing::error!("Account locked for client id {} transaction id {}", transaction.client_id, transaction.id);
-                    return Err(Error::new(ErrorKind::EngineError("Account locked".to_string())));
-                }
+    fn send_change(&self, transaction: &Transaction, account: &Account) {
+        if let Some(changes) = &self.changes {
+            let change = AccountChange { sequence: transaction.sequence, tx: transaction.id, account: account.clone() };
+            if changes.send(change).is_err() {
+                tracing::debug!("Change feed closed, dropping change of transaction {}", transaction.id);
+            }
+        }
+    }
+
+    async fn record_outcome(&self, transaction: &Transaction, status: OutcomeStatus, reason: Option<String>) {
+        self.add_outcome(Outcome::new(transaction, status, reason)).await;
+    }
 
-                self.apply_transaction(&mut account, &transaction).await?;
+    async fn record_rejection(&self, transaction: &Transaction, err: &Error) -> Outcome {
+        let mut outcome = Outcome::new(transaction, OutcomeStatus::Rejected, Some(err.to_string()));
+        match &*err.kind {
+            ErrorKind::RuleViolation(rule, _) | ErrorKind::FraudRejected(rule, _) => outcome.rule = Some(rule.clone()),
+            _ => {},
+        }
+        self.add_outcome(outcome.clone()).await;
+        outcome
+    }
 
-                self.store.update_account(&account).await?;
-                Ok(())
-            }.await;
-
-            match transaction_result {
-                Ok(_) => {},
-                Err(_) => {
-                    tracing::warn!("Rolling back transaction for tx {}", transaction.id);
-                    match transaction.kind {
-                        TransactionKind::Deposit | TransactionKind::Withdrawal => {
-                            if let Err(_) = self.store.delete_transaction(transaction.id).await {
-                                tracing::error!("Failed to rollback transaction: {}", transaction.id);
-                            }
-                        },
-
-                        TransactionKind::Dispute => {
-                            if let Err(_) = self.store.set_transaction_under_dispute(transaction.id, false).await {
-                                tracing::error!("Failed to rollback transaction: {}", transaction.id);
-                            }
-                        },
-
-                        TransactionKind::Resolve | TransactionKind::ChargeBack => {
-                            if let Err(_) = self.store.set_transaction_under_dispute(transaction.id, true).await {
-                                tracing::error!("Failed to rollback transaction: {}", transaction.id);
-                            }
-                        },
-                    };    
-                }
+    // record_activity counts an applied transaction in the client statistics.
+    // Disputes, resolves and chargebacks without effect on the account were
+    // ignored and are not counted, neither are releases and cancels themselves.
+    async fn record_activity(&self, transaction: &Transaction, changed: bool) {
+        let kind = match transaction.kind {
+            TransactionKind::Deposit => ActivityKind::Deposit,
+            TransactionKind::Withdrawal => ActivityKind::Withdrawal,
+            TransactionKind::Dispute if changed => ActivityKind::Dispute,
+            TransactionKind::Resolve if changed => ActivityKind::Resolve,
+            TransactionKind::ChargeBack if changed => ActivityKind::ChargeBack,
+            _ => return,
+        };
+        let amount = match kind {
+            ActivityKind::Deposit | ActivityKind::Withdrawal => transaction.amount,
+            _ => self.store.get_transaction(transaction.id).await.ok().and_then(|disputed| disputed.amount),
+        };
+        self.add_activity(Activity::new(transaction.client_id, kind, amount.unwrap_or_default())).await;
+    }
+
+    async fn add_activity(&self, activity: Activity) {
+        let client = activity.client;
+        if let Err(e) = self.store.record_activity(activity).await {
+            tracing::error!("Failed to record activity for client id {}: {}", client, e);
+        }
+    }
+
+    async fn add_outcome(&self, outcome: Outcome) {
+        let id = outcome.tx;
+        if let Err(e) = self.store.add_outcome(outcome).await {
+            tracing::error!("Failed to record outcome for transaction {}: {}", id, e);
+        }
+    }
+
+    // apply_transaction evaluates the rules and fraud hooks and applies the
+    // transaction on the account. Admin operations bypass rules and hooks.
+    pub async fn apply_transaction(&self, account: &mut Account, transaction: &Transaction) -> Result<Applied, Error> {
+        if !transaction.kind.is_admin() {
+            self.rules.evaluate(transaction)?;
+
+            match self.fraud_hooks.score(account, transaction) {
+                (Verdict::Allow, _) => {},
+                (Verdict::Reject(reason), hook) => {
+                    let hook = hook.unwrap_or_default().to_string();
+                    tracing::error!("Fraud hook {} rejected transaction {}: {}", hook, transaction.id, reason);
+                    return Err(Error::new(ErrorKind::FraudRejected(hook, reason)));
+                },
+                (Verdict::Hold(reason), hook) => {
+                    let hook = hook.unwrap_or_default().to_string();
+                    self.hold(account, transaction, &hook, &reason).await?;
+                    self.rules.record(transaction);
+                    return Ok(Applied::Held(hook, reason));
+                },
             }
         }
-        Ok(())
+
+        let result = self.execute(account, transaction).await;
+        if result.is_ok() {
+            self.rules.record(transaction);
+            self.fraud_hooks.observe(transaction);
+        }
+        result.map(|_| Applied::Completed)
+    }
+
+    // execute applies the transaction to the account without rules or fraud
+    // hooks, system transactions such as dispute expiries go through it directly.
+    async fn execute(&self, account: &mut Account, transaction: &Transaction) -> Result<(), Error> {
+        match transaction.kind {
+            TransactionKind::Deposit => self.deposit(account, &required_amount(transaction)?).await,
+            TransactionKind::Withdrawal => self.withdrawal(account, &required_amount(transaction)?).await,
+            TransactionKind::Dispute => self.dispute(account, transaction).await,
+            TransactionKind::Resolve => self.resolve(account, transaction).await,
+            TransactionKind::ChargeBack => self.chargeback(account, transaction).await,
+            TransactionKind::Release => self.release(account, transaction).await,
+            TransactionKind::Cancel => self.cancel(account, transaction).await,
+        }
     }
 
-    pub async fn apply_transaction(&self, account: &mut Account, transaction: &Transaction) -> Result<(), Error> {
+    // hold reserves the funds of a deposit or withdrawal in held and parks
+    // the transaction in the store until it is released or cancelled.
+    async fn hold(&self, account: &mut Account, transaction: &Transaction, hook: &str, reason: &str) -> Result<(), Error> {
+        let amount = required_amount(transaction)?;
         match transaction.kind {
-            TransactionKind::Deposit => { return self.deposit(account, &transaction.amount.unwrap()).await;},
-            TransactionKind::Withdrawal => { return self.withdrawal(account, &transaction.amount.unwrap()).await;},
-            TransactionKind::Dispute => { return self.dispute(account, &transaction).await;},
-            TransactionKind::Resolve => { return self.resolve(account, &transaction).await;},
-            TransactionKind::ChargeBack => { return self.chargeback(account, &transaction).await;},
+            TransactionKind::Deposit => {
+                account.held += amount;
+                account.total += amount;
+                // Held deposits can not be disputed until released.
+                self.store.delete_transaction(transaction.id).await?;
+            },
+            TransactionKind::Withdrawal => {
+                if account.available < amount {
+                    tracing::error!(?account, "Insufficient available funds");
+                    return Err(Error::new(ErrorKind::InsufficientAvailableFunds));
+                }
+                account.available -= amount;
+                account.held += amount;
+            },
+            _ => {
+                tracing::error!("Fraud hook {} can not hold transaction {} of kind {:?}", hook, transaction.id, transaction.kind);
+                return Err(Error::new(ErrorKind::FraudRejected(hook.to_string(), reason.to_string())));
+            },
         }
+        tracing::warn!("Fraud hook {} held transaction {}: {}", hook, transaction.id, reason);
+        self.store.hold_transaction(transaction.clone()).await
+    }
+
+    // release completes a held transaction.
+    async fn release(&self, account: &mut Account, info: &Transaction) -> Result<(), Error> {
+        let held = self.get_held(account, info).await?;
+        let amount = required_amount(&held)?;
+        account.held -= amount;
+        match held.kind {
+            TransactionKind::Deposit => account.available += amount,
+            _ => account.total -= amount,
+        }
+        self.store.remove_held_transaction(info.id).await?;
+        if held.kind == TransactionKind::Deposit {
+            self.store.add_transaction(held).await?;
+        }
+        tracing::info!("Released held transaction {}", info.id);
+        Ok(())
+    }
+
+    // cancel reverts a held transaction.
+    async fn cancel(&self, account: &mut Account, info: &Transaction) -> Result<(), Error> {
+        let held = self.get_held(account, info).await?;
+        let amount = required_amount(&held)?;
+        account.held -= amount;
+        match held.kind {
+            TransactionKind::Deposit => account.total -= amount,
+            _ => account.available += amount,
+        }
+        self.store.remove_held_transaction(info.id).await?;
+        tracing::info!("Cancelled held transaction {}", info.id);
+        Ok(())
+    }
+
+    async fn get_held(&self, account: &Account, info: &Transaction) -> Result<Transaction, Error> {
+        let held = self.store.get_held_transaction(info.id).await?;
+        if account.client != held.client_id || held.client_id != info.client_id {
+            tracing::error!(?account, "Wrong client_id in transaction: {}, expected: {}, got: {}", info.id, account.client, info.client_id);
+            return Err(Error::new(ErrorKind::WrongClientError(info.id, account.client, info.client_id)));
+        } else if account.held < required_amount(&held)? {
+            tracing::error!(?account, "Insufficient held funds");
+            return Err(Error::new(ErrorKind::InsufficientAvailableFunds));
+        }
+        Ok(held)
     }
 
     async fn deposit(&self, account: &mut Account, amount: &f32) -> Result<(), Error> {
@@ -113,27 +550,32 @@ where S: 'static+Send+Clone{
                 match &*e.kind {
                     ErrorKind::StoreError(_) => {
                         tracing::info!("Ignoring dispute no reference found for transaction {}", info.id);
-                        return Ok(())
+                        Ok(())
                     },
-                    _ => return Err(e),
+                    _ => Err(e),
                 }
                 
             },
             Ok(ref_tx) => {
                 if ref_tx.kind == TransactionKind::Deposit {
+                    let amount = required_amount(&ref_tx)?;
                     if account.client != ref_tx.client_id || ref_tx.client_id != info.client_id {
                         tracing::error!(?account, "Wrong client_id in transaction: {}, expected: {}, got: {}", info.id, account.client, info.client_id);
                         return Err(Error::new(ErrorKind::WrongClientError(info.id, account.client, info.client_id)));
                     } else if ref_tx.under_dispute {
                         tracing::error!(?account, "Double dispute for tx {}", info.id);
                         return Err(Error::new(ErrorKind::DoubleDispute(info.id)));
-                    } else if account.available < ref_tx.amount.unwrap() {
+                    } else if !self.dispute_policy.is_within_window(&ref_tx, info) {
+                        tracing::error!(?account, "Dispute window expired for tx {}", info.id);
+                        return Err(Error::new(ErrorKind::DisputeWindowExpired(info.id)));
+                    } else if account.available < amount {
                         tracing::error!(?account, "Insufficient available funds");
                         return Err(Error::new(ErrorKind::InsufficientAvailableFunds));
                     }
-                    account.available -= ref_tx.amount.unwrap();
-                    account.held += ref_tx.amount.unwrap();
+                    account.available -= amount;
+                    account.held += amount;
                     self.store.set_transaction_under_dispute(info.id, true).await?;
+                    self.store.set_transaction_disputed_at(info.id, info.timestamp).await?;
                 } else {
                     tracing::error!("Reference transaction {} is not a Deposit", info.id);
                     return Err(Error::new(ErrorKind::WrongTransactionRef(info.id)));
@@ -153,24 +595,25 @@ where S: 'static+Send+Clone{
                         tracing::info!("Ignoring resolve no reference found for transaction {}", info.id);
                         Ok(())
                     },
-                    _ => return Err(e),
+                    _ => Err(e),
                 }
                 
             },
             Ok(ref_tx) => {
                 if ref_tx.kind == TransactionKind::Deposit {
+                    let amount = required_amount(&ref_tx)?;
                     if account.client != ref_tx.client_id || ref_tx.client_id != info.client_id {
                         tracing::error!(?account, "Wrong client_id in transaction: {}, expected: {}, got: {}", info.id, account.client, info.client_id);
                         return Err(Error::new(ErrorKind::WrongClientError(info.id, account.client, info.client_id)));
-                    } else if account.held < ref_tx.amount.unwrap() {
+                    } else if account.held < amount {
                         tracing::error!(?account, "Insufficient available funds");
                         return Err(Error::new(ErrorKind::InsufficientAvailableFunds));
                     } else if !ref_tx.under_dispute {
                         tracing::info!("Ignoring resolve for transaction {}. Not under dispute", info.id);
                         return Ok(());
                     }
-                    account.held -= ref_tx.amount.unwrap();
-                    account.available += ref_tx.amount.unwrap();
+                    account.held -= amount;
+                    account.available += amount;
                     self.store.set_transaction_under_dispute(info.id, false).await?;
                 } else {
                     tracing::error!("Reference transaction {} is not a Deposit", info.id);
@@ -191,16 +634,17 @@ where S: 'static+Send+Clone{
                         tracing::info!("Ignoring chargeback no reference found for transaction {}", info.id);
                         Ok(())
                     },
-                    _ => return Err(e),
+                    _ => Err(e),
                 }
                 
             },
             Ok(ref_tx) => {
                 if ref_tx.kind == TransactionKind::Deposit {
+                    let amount = required_amount(&ref_tx)?;
                     if account.client != ref_tx.client_id || ref_tx.client_id != info.client_id {
                         tracing::error!(?account, "Wrong client_id in transaction: {}, expected: {}, got: {}", info.id, account.client, info.client_id);
                         return Err(Error::new(ErrorKind::WrongClientError(info.id, account.client, info.client_id)));
-                    } else if account.held < ref_tx.amount.unwrap() {
+                    } else if account.held < amount {
                         tracing::error!(?account, "Insufficient available funds");
                         return Err(Error::new(ErrorKind::InsufficientAvailableFunds));
                     } else if !ref_tx.under_dispute {
@@ -208,8 +652,8 @@ where S: 'static+Send+Clone{
                         return Ok(());
                     }
                     // if everything is fine: update the account
-                    account.held -= ref_tx.amount.unwrap();
-                    account.total -= ref_tx.amount.unwrap();
+                    account.held -= amount;
+                    account.total -= amount;
                     account.locked = true;
                     // set to not under dispute
                     self.store
@@ -226,14 +670,23 @@ where S: 'static+Send+Clone{
     }
 }
 
+// required_amount returns the amount of a deposit or withdrawal, which readers
+// require but the engine can be called without.
+fn required_amount(transaction: &Transaction) -> Result<f32, Error> {
+    transaction.amount.ok_or_else(|| Error::new(ErrorKind::EngineError(format!("Missing amount for transaction {}", transaction.id))))
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
+    use async_trait::async_trait;
+    use futures::StreamExt;
     use mem_store::mem_store::MemStore;
-    use models::{account::Account, store::Store, transactions::{Transaction, TransactionKind}, logger::create_span, infra::SpannedRuntime};
+    use models::{account::Account, error::{Error, ErrorKind}, store::Store, transactions::{Transaction, TransactionKind}, ids::{ClientId, TransactionId}, outcome::OutcomeStatus, stats::AccountStats, logger::create_span, infra::SpannedRuntime};
 
     use tracing_test::traced_test;
+    use crate::{dispute::{DisputePolicy, ExpiryAction}, fraud::{FraudHooks, LargeWithdrawalAfterDeposit}, middleware::Middleware, rules::{ClientActivity, MaxDeposit, Rule, RuleSet, VelocityLimit}};
     use super::Engine;
 
     #[test]
@@ -294,7 +747,7 @@ mod tests {
         let account = Account::load(1, 5.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_withdrawal_insufficient_funds_test(account, store, rtc));
-        assert!(logs_contain(format!("Insufficient available funds").as_str()));
+        assert!(logs_contain("Insufficient available funds"));
     }
 
     async fn run_withdrawal_insufficient_funds_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -364,8 +817,8 @@ mod tests {
         drop(tx);
         worker.await.unwrap();
 
-        let transaction = store.get_transaction(txn_id).await.unwrap();
-        assert_eq!(transaction.under_dispute, true);
+        let transaction = store.get_transaction(TransactionId(txn_id)).await.unwrap();
+        assert!(transaction.under_dispute);
 
         let account = store.get_account(account.client).await.unwrap();
         assert_eq!(account.available, 0.0);
@@ -382,7 +835,7 @@ mod tests {
         let account = Account::load(1, 10.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_dispute_on_wrong_transaction_test(account, store, rtc));
-        assert!(logs_contain(format!("Ignoring dispute no reference found for transaction").as_str()));
+        assert!(logs_contain("Ignoring dispute no reference found for transaction"));
     }
 
     async fn run_dispute_on_wrong_transaction_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -412,7 +865,7 @@ mod tests {
         let account = Account::load(1, 10.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_dispute_on_transaction_already_under_dispute_test(account, store, rtc));
-        assert!(logs_contain(format!("Double dispute for tx").as_str()));
+        assert!(logs_contain("Double dispute for tx"));
     }
 
     async fn run_dispute_on_transaction_already_under_dispute_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -443,8 +896,8 @@ mod tests {
         let account = Account::load(1, 5.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_dispute_with_insufficient_balance_test(account, store, rtc));
-        assert!(logs_contain(format!("Insufficient available funds").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Insufficient available funds"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_dispute_with_insufficient_balance_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -474,8 +927,8 @@ mod tests {
         let account_2 = Account::load(2, 10.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_dispute_on_wrong_clientid_test(account_1, account_2, store, rtc));
-        assert!(logs_contain(format!("Wrong client_id in transaction").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Wrong client_id in transaction"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_dispute_on_wrong_clientid_test(account_1: Account, account_2: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -491,11 +944,11 @@ mod tests {
         drop(tx);
         worker.await.unwrap();
 
-        let transaction = store.get_transaction(1).await.unwrap();
-        assert_eq!(transaction.under_dispute, false);
+        let transaction = store.get_transaction(TransactionId(1)).await.unwrap();
+        assert!(!transaction.under_dispute);
 
-        let transaction = store.get_transaction(2).await.unwrap();
-        assert_eq!(transaction.under_dispute, false);
+        let transaction = store.get_transaction(TransactionId(2)).await.unwrap();
+        assert!(!transaction.under_dispute);
     }
 
     #[test]
@@ -522,7 +975,7 @@ mod tests {
         worker.await.unwrap();
 
         let transaction = store.get_transaction(txn.id).await.unwrap();
-        assert_eq!(transaction.under_dispute, false);
+        assert!(!transaction.under_dispute);
 
         let account = store.get_account(account.client).await.unwrap();
         assert_eq!(account.available, 10.0);
@@ -539,7 +992,7 @@ mod tests {
         let account = Account::load(1, 0.0, 10.0, false);
         let store = MemStore::default();
         rt.block_on(run_resolve_on_wrong_transaction_test(account, store, rtc));
-        assert!(logs_contain(format!("Ignoring resolve no reference found for transaction").as_str()));
+        assert!(logs_contain("Ignoring resolve no reference found for transaction"));
     }
 
     async fn run_resolve_on_wrong_transaction_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -569,7 +1022,7 @@ mod tests {
         let account = Account::load(1, 0.0, 10.0, false);
         let store = MemStore::default();
         rt.block_on(run_resolve_on_transaction_not_under_resolve_test(account, store, rtc));
-        assert!(logs_contain(format!("Not under dispute").as_str()));
+        assert!(logs_contain("Not under dispute"));
     }
 
     async fn run_resolve_on_transaction_not_under_resolve_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -599,8 +1052,8 @@ mod tests {
         let account = Account::load(1, 5.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_resolve_with_insufficient_balance_test(account, store, rtc));
-        assert!(logs_contain(format!("Insufficient available funds").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Insufficient available funds"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_resolve_with_insufficient_balance_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -630,8 +1083,8 @@ mod tests {
         let account_2 = Account::load(2, 10.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_resolve_on_wrong_clientid_test(account_1, account_2, store, rtc));
-        assert!(logs_contain(format!("Wrong client_id in transaction").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Wrong client_id in transaction"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_resolve_on_wrong_clientid_test(account_1: Account, account_2: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -651,11 +1104,11 @@ mod tests {
         drop(tx);
         worker.await.unwrap();
 
-        let transaction = store.get_transaction(1).await.unwrap();
-        assert_eq!(transaction.under_dispute, true);
+        let transaction = store.get_transaction(TransactionId(1)).await.unwrap();
+        assert!(transaction.under_dispute);
 
-        let transaction = store.get_transaction(2).await.unwrap();
-        assert_eq!(transaction.under_dispute, true);
+        let transaction = store.get_transaction(TransactionId(2)).await.unwrap();
+        assert!(transaction.under_dispute);
     }
 
     #[test]
@@ -682,7 +1135,7 @@ mod tests {
         worker.await.unwrap();
 
         let transaction = store.get_transaction(txn.id).await.unwrap();
-        assert_eq!(transaction.under_dispute, false);
+        assert!(!transaction.under_dispute);
 
         let account = store.get_account(account.client).await.unwrap();
         assert_eq!(account.available, 0.0);
@@ -700,7 +1153,7 @@ mod tests {
         let account = Account::load(1, 0.0, 10.0, false);
         let store = MemStore::default();
         rt.block_on(run_chargeback_on_wrong_transaction_test(account, store, rtc));
-        assert!(logs_contain(format!("Ignoring chargeback no reference found for transaction").as_str()));
+        assert!(logs_contain("Ignoring chargeback no reference found for transaction"));
     }
 
     async fn run_chargeback_on_wrong_transaction_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -730,7 +1183,7 @@ mod tests {
         let account = Account::load(1, 0.0, 10.0, false);
         let store = MemStore::default();
         rt.block_on(run_chargeback_on_transaction_not_under_chargeback_test(account, store, rtc));
-        assert!(logs_contain(format!("Not under dispute").as_str()));
+        assert!(logs_contain("Not under dispute"));
     }
 
     async fn run_chargeback_on_transaction_not_under_chargeback_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -760,8 +1213,8 @@ mod tests {
         let account = Account::load(1, 5.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_chargeback_with_insufficient_balance_test(account, store, rtc));
-        assert!(logs_contain(format!("Insufficient available funds").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Insufficient available funds"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_chargeback_with_insufficient_balance_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -791,8 +1244,8 @@ mod tests {
         let account_2 = Account::load(2, 10.0, 0.0, false);
         let store = MemStore::default();
         rt.block_on(run_chargeback_on_wrong_clientid_test(account_1, account_2, store, rtc));
-        assert!(logs_contain(format!("Wrong client_id in transaction").as_str()));
-        assert!(logs_contain(format!("Rolling back transaction for tx").as_str()));
+        assert!(logs_contain("Wrong client_id in transaction"));
+        assert!(logs_contain("Rolling back transaction for tx"));
     }
 
     async fn run_chargeback_on_wrong_clientid_test(account_1: Account, account_2: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
@@ -812,10 +1265,468 @@ mod tests {
         drop(tx);
         worker.await.unwrap();
 
-        let transaction = store.get_transaction(1).await.unwrap();
-        assert_eq!(transaction.under_dispute, true);
+        let transaction = store.get_transaction(TransactionId(1)).await.unwrap();
+        assert!(transaction.under_dispute);
 
-        let transaction = store.get_transaction(2).await.unwrap();
-        assert_eq!(transaction.under_dispute, true);
+        let transaction = store.get_transaction(TransactionId(2)).await.unwrap();
+        assert!(transaction.under_dispute);
     }
-}
\ No newline at end of file
+
+    #[traced_test]
+    #[test]
+    fn test_dispute_outside_window() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let rtc = rt.clone();
+        let account = Account::load(1, 10.0, 0.0, false);
+        let store = MemStore::default();
+        rt.block_on(run_dispute_outside_window_test(account, store, rtc));
+        assert!(logs_contain("Dispute window expired for tx"));
+    }
+
+    async fn run_dispute_outside_window_test(account: Account, store: MemStore, rt: Arc<SpannedRuntime>) {
+        store.add_transaction(Transaction::new(TransactionKind::Deposit, 1, 2, Some(10.0)).with_timestamp(1000)).await.unwrap();
+        store.update_account(&account).await.unwrap();
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let policy = DisputePolicy::new(Some(100), None, ExpiryAction::Resolve);
+        let worker = Engine::new(store.clone()).with_dispute_policy(policy).start(rt.clone(), rx).await;
+
+        tx.send(Transaction::new(TransactionKind::Dispute, 1, 2, None).with_timestamp(1200)).await.unwrap();
+
+        drop(tx);
+        worker.await.unwrap();
+
+        let transaction = store.get_transaction(TransactionId(2)).await.unwrap();
+        assert!(!transaction.under_dispute);
+
+        let account = store.get_account(account.client).await.unwrap();
+        assert_eq!(account.available, 10.0);
+        assert_eq!(account.held, 0.0);
+
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].status, OutcomeStatus::Rejected);
+    }
+
+    #[test]
+    fn test_dispute_deadline_auto_resolve() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let rtc = rt.clone();
+        let store = MemStore::default();
+        rt.block_on(run_dispute_deadline_test(store, ExpiryAction::Resolve, rtc))
+    }
+
+    #[test]
+    fn test_dispute_deadline_auto_chargeback() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let rtc = rt.clone();
+        let store = MemStore::default();
+        rt.block_on(run_dispute_deadline_test(store, ExpiryAction::ChargeBack, rtc))
+    }
+
+    async fn run_dispute_deadline_test(store: MemStore, on_expiry: ExpiryAction, rt: Arc<SpannedRuntime>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let policy = DisputePolicy::new(None, Some(60), on_expiry.clone());
+        let worker = Engine::new(store.clone()).with_dispute_policy(policy).start(rt.clone(), rx).await;
+
+        tx.send(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000)).await.unwrap();
+        tx.send(Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1010)).await.unwrap();
+        tx.send(Transaction::new(TransactionKind::Deposit, 1, 2, Some(5.0)).with_timestamp(1030)).await.unwrap();
+        tx.send(Transaction::new(TransactionKind::Deposit, 1, 3, Some(5.0)).with_timestamp(1100)).await.unwrap();
+
+        drop(tx);
+        worker.await.unwrap();
+
+        let transaction = store.get_transaction(TransactionId(1)).await.unwrap();
+        assert!(!transaction.under_dispute);
+
+        let account = store.get_account(ClientId(1)).await.unwrap();
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes.len(), 5);
+        assert_eq!(outcomes[3].tx, TransactionId(1));
+        assert_eq!(outcomes[3].timestamp, Some(1100));
+        match on_expiry {
+            ExpiryAction::Resolve => {
+                assert_eq!(outcomes[3].status, OutcomeStatus::AutoResolved);
+                assert_eq!(outcomes[4].status, OutcomeStatus::Applied);
+                assert_eq!(account.available, 20.0);
+                assert_eq!(account.held, 0.0);
+                assert!(!account.locked);
+            },
+            ExpiryAction::ChargeBack => {
+                assert_eq!(outcomes[3].status, OutcomeStatus::AutoChargedBack);
+                assert_eq!(outcomes[4].status, OutcomeStatus::Rejected);
+                assert_eq!(account.available, 5.0);
+                assert_eq!(account.held, 0.0);
+                assert!(account.locked);
+            },
+        }
+    }
+
+    #[test]
+    fn test_expire_all_disputes() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_expire_all_disputes_test(store))
+    }
+
+    async fn run_expire_all_disputes_test(store: MemStore) {
+        let policy = DisputePolicy::new(None, Some(60), ExpiryAction::ChargeBack);
+        let engine = Engine::new(store.clone()).with_dispute_policy(policy);
+
+        // Client 1 has no transaction after its dispute, client 2's dispute is still within its deadline and stays open.
+        engine.process(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000)).await;
+        engine.process(&Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1010)).await;
+        engine.process(&Transaction::new(TransactionKind::Deposit, 2, 2, Some(5.0)).with_timestamp(1050)).await;
+        engine.process(&Transaction::new(TransactionKind::Dispute, 2, 2, None).with_timestamp(1060)).await;
+
+        engine.expire_all_disputes(1100).await.unwrap();
+
+        assert!(!store.get_transaction(TransactionId(1)).await.unwrap().under_dispute);
+        assert!(store.get_transaction(TransactionId(2)).await.unwrap().under_dispute);
+        let account = store.get_account(ClientId(1)).await.unwrap();
+        assert_eq!(account.available, 0.0);
+        assert_eq!(account.held, 0.0);
+        assert!(account.locked);
+
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes.len(), 5);
+        assert_eq!(outcomes[4].tx, TransactionId(1));
+        assert_eq!(outcomes[4].status, OutcomeStatus::AutoChargedBack);
+        assert_eq!(outcomes[4].timestamp, Some(1100));
+    }
+
+    // NoResolve rejects every resolve of a client.
+    struct NoResolve;
+
+    impl Rule for NoResolve {
+        fn name(&self) -> &'static str {
+            "no_resolve"
+        }
+
+        fn check(&self, _activity: &ClientActivity, transaction: &Transaction) -> Result<(), String> {
+            match transaction.kind {
+                TransactionKind::Resolve => Err("resolves are not allowed".to_string()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dispute_expiry_skips_rules() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_dispute_expiry_skips_rules_test(store))
+    }
+
+    async fn run_dispute_expiry_skips_rules_test(store: MemStore) {
+        let policy = DisputePolicy::new(None, Some(60), ExpiryAction::Resolve);
+        let rules = Arc::new(RuleSet::new(vec![Box::new(NoResolve), Box::new(VelocityLimit::new(3, 1000))]));
+        let engine = Engine::new(store.clone()).with_dispute_policy(policy).with_rules(rules);
+
+        engine.process(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000)).await;
+        engine.process(&Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1010)).await;
+        // The expiry is applied before deposit 2 even though a rule rejects
+        // resolves, and it does not count toward the velocity limit.
+        let processed = engine.process(&Transaction::new(TransactionKind::Deposit, 1, 2, Some(5.0)).with_timestamp(1100)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        assert_eq!(processed.account.available, 15.0);
+        let processed = engine.process(&Transaction::new(TransactionKind::Deposit, 1, 3, Some(5.0)).with_timestamp(1101)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+        assert_eq!(processed.outcome.rule.as_deref(), Some("velocity_limit"));
+
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes[2].status, OutcomeStatus::AutoResolved);
+        assert_eq!(outcomes[2].tx, TransactionId(1));
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_rule_rejection() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let rtc = rt.clone();
+        let store = MemStore::default();
+        rt.block_on(run_rule_rejection_test(store, rtc));
+        assert!(logs_contain("Rule max_deposit rejected transaction 2"));
+        assert!(logs_contain("Rolling back transaction for tx"));
+    }
+
+    async fn run_rule_rejection_test(store: MemStore, rt: Arc<SpannedRuntime>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let rules = Arc::new(RuleSet::new(vec![Box::new(MaxDeposit::new(50.0))]));
+        let worker = Engine::new(store.clone()).with_rules(rules).start(rt.clone(), rx).await;
+
+        tx.send(Transaction::new(TransactionKind::Deposit, 1, 1, Some(50.0))).await.unwrap();
+        tx.send(Transaction::new(TransactionKind::Deposit, 1, 2, Some(60.0))).await.unwrap();
+
+        drop(tx);
+        worker.await.unwrap();
+
+        assert!(store.get_transaction(TransactionId(2)).await.is_err());
+
+        let account = store.get_account(ClientId(1)).await.unwrap();
+        assert_eq!(account.available, 50.0);
+
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes[1].status, OutcomeStatus::Rejected);
+        assert_eq!(outcomes[1].rule, Some("max_deposit".to_string()));
+    }
+
+    #[test]
+    fn test_hold_and_release() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_hold_test(store, TransactionKind::Release))
+    }
+
+    #[test]
+    fn test_hold_and_cancel() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_hold_test(store, TransactionKind::Cancel))
+    }
+
+    async fn run_hold_test(store: MemStore, admin: TransactionKind) {
+        let hooks = Arc::new(FraudHooks::new(vec![Box::new(LargeWithdrawalAfterDeposit::new(0.5, 60))]));
+        let engine = Engine::new(store.clone()).with_fraud_hooks(hooks);
+
+        engine.process(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(100.0)).with_timestamp(1000)).await;
+        let processed = engine.process(&Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(80.0)).with_timestamp(1010)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Held);
+        assert_eq!(processed.outcome.rule, Some("large_withdrawal_after_deposit".to_string()));
+        let processed = engine.process(&Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(30.0))).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+
+        let held = store.get_held_transactions().await.unwrap();
+        assert_eq!(held[0].id, TransactionId(2));
+        assert_eq!(processed.account.available, 20.0);
+        assert_eq!(processed.account.held, 80.0);
+        assert_eq!(processed.account.total, 100.0);
+
+        let processed = engine.process(&Transaction::new(admin.clone(), 1, 2, None)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        let processed = engine.process(&Transaction::new(admin.clone(), 1, 2, None)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+
+        assert!(store.get_held_transactions().await.unwrap().is_empty());
+        let account = processed.account;
+        let stats = engine.stats().await.unwrap().collect::<Vec<_>>().await;
+        // Only a released withdrawal counts, as the withdrawal it held. The rejected withdrawal is counted either way.
+        match admin {
+            TransactionKind::Release => {
+                assert_eq!(account.available, 20.0);
+                assert_eq!(account.total, 20.0);
+                assert_eq!((stats[0].withdrawals, stats[0].withdrawn), (1, 80.0));
+            },
+            _ => {
+                assert_eq!(account.available, 100.0);
+                assert_eq!(account.total, 100.0);
+                assert_eq!((stats[0].withdrawals, stats[0].withdrawn), (0, 0.0));
+            },
+        }
+        assert_eq!((stats[0].deposits, stats[0].deposited), (1, 100.0));
+        assert_eq!(stats[0].rejected, 2);
+        assert_eq!(account.held, 0.0);
+    }
+
+    #[test]
+    fn test_release_on_locked_account() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_release_on_locked_account_test(store))
+    }
+
+    // Admin operations go through the lock check, so funds held before the
+    // account was locked can still be released or cancelled.
+    async fn run_release_on_locked_account_test(store: MemStore) {
+        let hooks = Arc::new(FraudHooks::new(vec![Box::new(LargeWithdrawalAfterDeposit::new(0.5, 60))]));
+        let engine = Engine::new(store.clone()).with_fraud_hooks(hooks);
+
+        engine.process(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(100.0)).with_timestamp(1000)).await;
+        engine.process(&Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(80.0)).with_timestamp(1010)).await;
+        let mut account = store.get_account(ClientId(1)).await.unwrap();
+        account.locked = true;
+        store.update_account(&account).await.unwrap();
+
+        let processed = engine.process(&Transaction::new(TransactionKind::Deposit, 1, 3, Some(5.0))).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+        let processed = engine.process(&Transaction::new(TransactionKind::Release, 1, 2, None)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        assert_eq!(processed.account.available, 20.0);
+        assert_eq!(processed.account.held, 0.0);
+        assert_eq!(processed.account.total, 20.0);
+        assert!(processed.account.locked);
+    }
+
+    #[test]
+    fn test_process() {
+        let span = create_span();
+        let rt = Arc::new(models::infra::get_runtime(1, 1, span).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_process_test(store))
+    }
+
+    async fn run_process_test(store: MemStore) {
+        let engine = Engine::new(store.clone());
+
+        let processed = engine.process(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        assert_eq!(processed.account.available, 10.0);
+
+        let processed = engine.process(&Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(15.0))).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+        assert_eq!(processed.outcome.reason, Some("Insufficient Available Funds".to_string()));
+        assert_eq!(processed.account.available, 10.0);
+        assert_eq!(processed.account.total, 10.0);
+
+        // A deposit without amount is rejected rather than crashing the engine.
+        let processed = engine.process(&Transaction::new(TransactionKind::Deposit, 1, 3, None)).await;
+        assert_eq!(processed.outcome.status, OutcomeStatus::Rejected);
+        assert_eq!(processed.outcome.reason, Some("Engine error Missing amount for transaction 3".to_string()));
+        assert_eq!(processed.account.total, 10.0);
+        assert!(store.get_transaction(TransactionId(3)).await.is_err());
+
+        let outcomes = store.get_all_outcomes().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(outcomes.len(), 3);
+    }
+
+    #[test]
+    fn test_process_blocking() {
+        let span = create_span();
+        let rt = models::infra::get_runtime(1, 1, span).unwrap();
+        let engine = Engine::new(MemStore::default());
+
+        let processed = engine.process_blocking(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))).unwrap();
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        let processed = engine.process_blocking(&Transaction::new(TransactionKind::Dispute, 1, 1, None)).unwrap();
+        assert_eq!(processed.outcome.status, OutcomeStatus::Applied);
+        assert_eq!(processed.account.available, 0.0);
+        assert_eq!(processed.account.held, 10.0);
+
+        // Within a runtime process_blocking refuses to block its thread.
+        let result = rt.block_on(async { engine.process_blocking(&Transaction::new(TransactionKind::Deposit, 1, 2, Some(5.0))) });
+        assert!(matches!(&*result.unwrap_err().kind, ErrorKind::EngineError(_)));
+    }
+
+    #[test]
+    fn test_concurrent_process() {
+        let rt = Arc::new(models::infra::get_runtime(4, 1, create_span()).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_concurrent_process_test(store, rt.clone()))
+    }
+
+    // Yield lets other tasks run between loading and updating the account.
+    struct Yield;
+
+    #[async_trait]
+    impl Middleware for Yield {
+        fn name(&self) -> &'static str {
+            "yield"
+        }
+
+        async fn pre_apply(&self, _account: &Account, _transaction: &Transaction) -> Result<(), Error> {
+            tokio::task::yield_now().await;
+            Ok(())
+        }
+    }
+
+    // Withdrawals racing on one client must not both see the funds.
+    async fn run_concurrent_process_test(store: MemStore, rt: Arc<SpannedRuntime>) {
+        let engine = Engine::new(store.clone()).with_middleware(Arc::new(Yield));
+        for client in 1..=4u32 {
+            engine.process(&Transaction::new(TransactionKind::Deposit, client, u64::from(client), Some(10.0))).await;
+        }
+        let tasks = (0..40u64)
+            .map(|id| {
+                let engine = engine.clone();
+                let client = (id % 4) as u32 + 1;
+                rt.spawn(async move {
+                    engine.process(&Transaction::new(TransactionKind::Withdrawal, client, 100 + id, Some(1.0))).await.outcome.status
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut applied = 0;
+        for task in tasks {
+            if task.await.unwrap() == OutcomeStatus::Applied {
+                applied += 1;
+            }
+        }
+        assert_eq!(applied, 40);
+        let accounts = store.get_all_accounts().await.unwrap().collect::<Vec<_>>().await;
+        assert!(accounts.iter().all(|a| a.available == 0.0 && a.total == 0.0));
+        assert!(engine.locks.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_change_feed() {
+        let (changes, mut feed) = tokio::sync::mpsc::unbounded_channel();
+        let engine = Engine::new(MemStore::default())
+            .with_dispute_policy(DisputePolicy::new(None, Some(100), ExpiryAction::Resolve))
+            .with_change_feed(changes);
+
+        engine.process_blocking(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000)).unwrap();
+        engine.process_blocking(&Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(50.0)).with_timestamp(1010)).unwrap();
+        engine.process_blocking(&Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1020)).unwrap();
+        engine.process_blocking(&Transaction::new(TransactionKind::Deposit, 1, 3, Some(5.0)).with_timestamp(1200)).unwrap();
+
+        let mut changes = Vec::new();
+        while let Ok(change) = feed.try_recv() {
+            changes.push((change.tx.0, change.account.available, change.account.held));
+        }
+        // The rejected withdrawal changes nothing, the expired dispute is resolved before the last deposit.
+        assert_eq!(changes, vec![(1, 10.0, 0.0), (1, 0.0, 10.0), (1, 10.0, 0.0), (3, 15.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_activity_stats() {
+        let span = create_span();
+        let rt = models::infra::get_runtime(1, 1, span).unwrap();
+        let engine = Engine::new(MemStore::default())
+            .with_dispute_policy(DisputePolicy::new(None, Some(100), ExpiryAction::ChargeBack));
+
+        for transaction in [
+            Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000),
+            Transaction::new(TransactionKind::Deposit, 1, 2, Some(5.0)).with_timestamp(1000),
+            Transaction::new(TransactionKind::Deposit, 1, 3, Some(2.5)).with_timestamp(1000),
+            Transaction::new(TransactionKind::Withdrawal, 1, 4, Some(2.5)).with_timestamp(1000),
+            Transaction::new(TransactionKind::Withdrawal, 1, 5, Some(50.0)).with_timestamp(1000),
+            Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1000),
+            Transaction::new(TransactionKind::Dispute, 1, 2, None).with_timestamp(1000),
+            Transaction::new(TransactionKind::Resolve, 1, 2, None).with_timestamp(1000),
+            // Ignored, the deposit is not under dispute anymore.
+            Transaction::new(TransactionKind::Resolve, 1, 2, None).with_timestamp(1000),
+            Transaction::new(TransactionKind::Dispute, 1, 2, None).with_timestamp(1050),
+            Transaction::new(TransactionKind::Deposit, 2, 6, Some(1.0)).with_timestamp(1000),
+            // The dispute on tx 1 is charged back first, which locks the account and rejects this one.
+            Transaction::new(TransactionKind::Dispute, 1, 9, None).with_timestamp(1120),
+        ] {
+            engine.process_blocking(&transaction).unwrap();
+        }
+
+        let stats = rt.block_on(async { engine.stats().await.unwrap().collect::<Vec<_>>().await });
+        assert_eq!(stats, vec![
+            AccountStats {
+                client: ClientId(1),
+                deposits: 3,
+                deposited: 17.5,
+                withdrawals: 1,
+                withdrawn: 2.5,
+                open_disputes: 1,
+                disputed: 5.0,
+                chargebacks: 1,
+                charged_back: 10.0,
+                rejected: 2,
+                rejected_amount: 50.0,
+            },
+            AccountStats { client: ClientId(2), deposits: 1, deposited: 1.0, ..AccountStats::default() },
+        ]);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::{HashMap, VecDeque}, sync::Mutex};
+
+use models::{account::Account, ids::ClientId, transactions::{Transaction, TransactionKind}};
+
+// Verdict is the decision of a fraud hook on a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Reject(String),
+    // Hold parks the transaction with its funds reserved until an admin releases or cancels it.
+    Hold(String),
+}
+
+// FraudHook scores transactions before they are applied by the engine.
+pub trait FraudHook: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn score(&self, account: &Account, transaction: &Transaction) -> Verdict;
+
+    // Called for every transaction applied by the engine.
+    fn observe(&self, _transaction: &Transaction) {}
+}
+
+// FraudHooks runs all hooks in order, the first verdict which is not Allow wins.
+#[derive(Default)]
+pub struct FraudHooks {
+    hooks: Vec<Box<dyn FraudHook>>,
+}
+
+impl FraudHooks {
+    pub fn new(hooks: Vec<Box<dyn FraudHook>>) -> Self {
+        Self { hooks }
+    }
+
+    // Returns the verdict along with the name of the hook which gave it.
+    pub fn score(&self, account: &Account, transaction: &Transaction) -> (Verdict, Option<&'static str>) {
+        for hook in self.hooks.iter() {
+            match hook.score(account, transaction) {
+                Verdict::Allow => continue,
+                verdict => return (verdict, Some(hook.name())),
+            }
+        }
+        (Verdict::Allow, None)
+    }
+
+    pub fn observe(&self, transaction: &Transaction) {
+        for hook in self.hooks.iter() {
+            hook.observe(transaction);
+        }
+    }
+}
+
+// LargeWithdrawalAfterDeposit holds withdrawals of at least ratio times the
+// last deposit of the client when made within window seconds after it.
+pub struct LargeWithdrawalAfterDeposit {
+    ratio: f32,
+    window: u64,
+    last_deposits: Mutex<HashMap<ClientId, (u64, f32)>>,
+}
+
+impl LargeWithdrawalAfterDeposit {
+    pub fn new(ratio: f32, window: u64) -> Self {
+        Self { ratio, window, last_deposits: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl FraudHook for LargeWithdrawalAfterDeposit {
+    fn name(&self) -> &'static str {
+        "large_withdrawal_after_deposit"
+    }
+
+    fn score(&self, _account: &Account, transaction: &Transaction) -> Verdict {
+        let (timestamp, amount) = match (&transaction.kind, transaction.timestamp, transaction.amount) {
+            (TransactionKind::Withdrawal, Some(timestamp), Some(amount)) => (timestamp, amount),
+            _ => return Verdict::Allow,
+        };
+        let last_deposits = self.last_deposits.lock().expect("fraud hook lock poisoned");
+        match last_deposits.get(&transaction.client_id) {
+            Some((deposited_at, deposit)) if timestamp.saturating_sub(*deposited_at) <= self.window && amount >= deposit * self.ratio => {
+                Verdict::Hold(format!("withdrawal of {} within {} seconds of deposit of {}", amount, self.window, deposit))
+            },
+            _ => Verdict::Allow,
+        }
+    }
+
+    fn observe(&self, transaction: &Transaction) {
+        if let (TransactionKind::Deposit, Some(timestamp), Some(amount)) = (&transaction.kind, transaction.timestamp, transaction.amount) {
+            self.last_deposits.lock().expect("fraud hook lock poisoned").insert(transaction.client_id, (timestamp, amount));
+        }
+    }
+}
+
+// RapidDisputes rejects disputes of a client which already opened max_disputes within window seconds.
+pub struct RapidDisputes {
+    max_disputes: usize,
+    window: u64,
+    disputes: Mutex<HashMap<ClientId, VecDeque<u64>>>,
+}
+
+impl RapidDisputes {
+    pub fn new(max_disputes: usize, window: u64) -> Self {
+        Self { max_disputes, window, disputes: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl FraudHook for RapidDisputes {
+    fn name(&self) -> &'static str {
+        "rapid_disputes"
+    }
+
+    fn score(&self, _account: &Account, transaction: &Transaction) -> Verdict {
+        let timestamp = match (&transaction.kind, transaction.timestamp) {
+            (TransactionKind::Dispute, Some(timestamp)) => timestamp,
+            _ => return Verdict::Allow,
+        };
+        let disputes = self.disputes.lock().expect("fraud hook lock poisoned");
+        let count = disputes.get(&transaction.client_id)
+            .map_or(0, |d| d.iter().filter(|t| timestamp.saturating_sub(**t) < self.window).count());
+        if count >= self.max_disputes {
+            return Verdict::Reject(format!("{} disputes within {} seconds", count, self.window));
+        }
+        Verdict::Allow
+    }
+
+    fn observe(&self, transaction: &Transaction) {
+        if let (TransactionKind::Dispute, Some(timestamp)) = (&transaction.kind, transaction.timestamp) {
+            let mut disputes = self.disputes.lock().expect("fraud hook lock poisoned");
+            let client_disputes = disputes.entry(transaction.client_id).or_default();
+            client_disputes.push_back(timestamp);
+            while client_disputes.front().is_some_and(|t| timestamp.saturating_sub(*t) >= self.window) {
+                client_disputes.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::{account::Account, transactions::{Transaction, TransactionKind}};
+
+    use super::{FraudHook, FraudHooks, LargeWithdrawalAfterDeposit, RapidDisputes, Verdict};
+
+    #[test]
+    fn test_large_withdrawal_after_deposit() {
+        let hook = LargeWithdrawalAfterDeposit::new(0.9, 60);
+        let account = Account::load(1, 100.0, 0.0, false);
+        hook.observe(&Transaction::new(TransactionKind::Deposit, 1, 1, Some(100.0)).with_timestamp(1000));
+
+        let withdrawal = Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(95.0)).with_timestamp(1030);
+        assert!(matches!(hook.score(&account, &withdrawal), Verdict::Hold(_)));
+
+        let withdrawal = Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(50.0)).with_timestamp(1030);
+        assert_eq!(hook.score(&account, &withdrawal), Verdict::Allow);
+
+        let withdrawal = Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(95.0)).with_timestamp(1100);
+        assert_eq!(hook.score(&account, &withdrawal), Verdict::Allow);
+
+        let withdrawal = Transaction::new(TransactionKind::Withdrawal, 2, 3, Some(95.0)).with_timestamp(1030);
+        assert_eq!(hook.score(&account, &withdrawal), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_rapid_disputes() {
+        let hooks = FraudHooks::new(vec![Box::new(RapidDisputes::new(2, 60))]);
+        let account = Account::load(1, 100.0, 0.0, false);
+        hooks.observe(&Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1000));
+        hooks.observe(&Transaction::new(TransactionKind::Dispute, 1, 2, None).with_timestamp(1010));
+
+        let (verdict, hook) = hooks.score(&account, &Transaction::new(TransactionKind::Dispute, 1, 3, None).with_timestamp(1020));
+        assert!(matches!(verdict, Verdict::Reject(_)));
+        assert_eq!(hook, Some("rapid_disputes"));
+
+        let (verdict, hook) = hooks.score(&account, &Transaction::new(TransactionKind::Dispute, 1, 3, None).with_timestamp(1065));
+        assert_eq!(verdict, Verdict::Allow);
+        assert_eq!(hook, None);
+    }
+}
@@ -1 +1,5 @@
 pub mod engine;
+pub mod dispute;
+pub mod fraud;
+pub mod middleware;
+pub mod rules;
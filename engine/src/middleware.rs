@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use models::{account::Account, error::{Error, ErrorKind}, transactions::Transaction};
+
+use crate::engine::Applied;
+
+// Middleware hooks into the engine processing of every transaction.
+// Middlewares run in registration order.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Called before the transaction is written to the store, an error rejects the transaction.
+    async fn pre_apply(&self, _account: &Account, _transaction: &Transaction) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Called once the transaction has been applied and the account updated.
+    async fn post_apply(&self, _account: &Account, _transaction: &Transaction, _applied: &Applied) {}
+
+    // Called when the transaction is rejected, after any rollback.
+    async fn on_reject(&self, _transaction: &Transaction, _err: &Error) {}
+}
+
+// AmountValidation rejects transactions with a negative amount.
+pub struct AmountValidation;
+
+#[async_trait]
+impl Middleware for AmountValidation {
+    fn name(&self) -> &'static str {
+        "amount_validation"
+    }
+
+    async fn pre_apply(&self, _account: &Account, transaction: &Transaction) -> Result<(), Error> {
+        if !transaction.is_valid_amount() {
+            tracing::error!("Transaction with id {} has negative amount", transaction.id);
+            return Err(Error::new(ErrorKind::EngineError("Negative amount".to_string())));
+        }
+        Ok(())
+    }
+}
+
+// LockCheck rejects transactions on locked accounts. Admin operations are let
+// through so funds held for review before the lock are not stuck in held.
+pub struct LockCheck;
+
+#[async_trait]
+impl Middleware for LockCheck {
+    fn name(&self) -> &'static str {
+        "lock_check"
+    }
+
+    async fn pre_apply(&self, account: &Account, transaction: &Transaction) -> Result<(), Error> {
+        if account.locked && !transaction.kind.is_admin() {
+            tracing::error!("Account locked for client id {} transaction id {}", transaction.client_id, transaction.id);
+            return Err(Error::new(ErrorKind::EngineError("Account locked".to_string())));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use mem_store::mem_store::MemStore;
+    use models::{account::Account, error::{Error, ErrorKind}, ids::ClientId, logger::create_span, store::Store, transactions::{Transaction, TransactionKind}};
+
+    use crate::engine::{Applied, Engine};
+    use super::Middleware;
+
+    // Audit records every hook call and rejects withdrawals above a threshold.
+    #[derive(Default)]
+    struct Audit {
+        calls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Middleware for Audit {
+        fn name(&self) -> &'static str {
+            "audit"
+        }
+
+        async fn pre_apply(&self, _account: &Account, transaction: &Transaction) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(format!("pre {}", transaction.id));
+            if transaction.kind == TransactionKind::Withdrawal && transaction.amount > Some(50.0) {
+                return Err(Error::new(ErrorKind::EngineError("Withdrawal above 50".to_string())));
+            }
+            Ok(())
+        }
+
+        async fn post_apply(&self, account: &Account, transaction: &Transaction, _applied: &Applied) {
+            self.calls.lock().unwrap().push(format!("post {} {}", transaction.id, account.available));
+        }
+
+        async fn on_reject(&self, transaction: &Transaction, err: &Error) {
+            self.calls.lock().unwrap().push(format!("reject {} {}", transaction.id, err));
+        }
+    }
+
+    #[test]
+    fn test_middleware_chain() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let store = MemStore::default();
+            let audit = Arc::new(Audit::default());
+            let (tx, rx) = tokio::sync::mpsc::channel(10);
+            let worker = Engine::new(store.clone()).with_middleware(audit.clone()).start(rtc, rx).await;
+
+            tx.send(Transaction::new(TransactionKind::Deposit, 1, 1, Some(100.0))).await.unwrap();
+            tx.send(Transaction::new(TransactionKind::Withdrawal, 1, 2, Some(60.0))).await.unwrap();
+            tx.send(Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(-1.0))).await.unwrap();
+
+            drop(tx);
+            worker.await.unwrap();
+
+            let account = store.get_account(ClientId(1)).await.unwrap();
+            assert_eq!(account.available, 100.0);
+            assert_eq!(*audit.calls.lock().unwrap(), vec![
+                "pre 1".to_string(),
+                "post 1 100".to_string(),
+                "pre 2".to_string(),
+                "reject 2 Engine error Withdrawal above 50".to_string(),
+                "reject 3 Engine error Negative amount".to_string(),
+            ]);
+        })
+    }
+}
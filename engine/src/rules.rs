@@ -0,0 +1,368 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, path::Path, sync::Mutex};
+
+use models::{error::{Error, ErrorKind}, ids::ClientId, transactions::{Transaction, TransactionKind}};
+use serde::Deserialize;
+
+const DAY_SECS: u64 = 86400;
+
+// Activity is a transaction applied for a client, kept for time based rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    pub timestamp: u64,
+    pub kind: TransactionKind,
+    pub amount: f32,
+}
+
+// ClientActivity holds the recent timestamped transactions applied for a client.
+#[derive(Debug, Clone, Default)]
+pub struct ClientActivity {
+    events: VecDeque<Activity>,
+}
+
+impl ClientActivity {
+    pub fn record(&mut self, transaction: &Transaction) {
+        if let Some(timestamp) = transaction.timestamp {
+            self.events.push_back(Activity {
+                timestamp,
+                kind: transaction.kind.clone(),
+                amount: transaction.amount.unwrap_or_default(),
+            });
+        }
+    }
+
+    // Drops events older than the given timestamp.
+    pub fn prune(&mut self, before: u64) {
+        while self.events.front().is_some_and(|e| e.timestamp < before) {
+            self.events.pop_front();
+        }
+    }
+
+    pub fn withdrawn_on_day(&self, day: u64) -> f32 {
+        self.events.iter()
+            .filter(|e| e.kind == TransactionKind::Withdrawal && e.timestamp / DAY_SECS == day)
+            .map(|e| e.amount)
+            .sum()
+    }
+
+    pub fn count_since(&self, since: u64) -> usize {
+        self.events.iter().filter(|e| e.timestamp >= since).count()
+    }
+}
+
+// Rule is a check evaluated by the engine before a transaction mutates an account.
+pub trait Rule: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Returns the reason of the rejection when the transaction breaks the rule.
+    fn check(&self, activity: &ClientActivity, transaction: &Transaction) -> Result<(), String>;
+
+    // Seconds of client activity the rule needs to look back on.
+    fn retention(&self) -> u64 {
+        0
+    }
+}
+
+pub struct BlockedClients {
+    clients: HashSet<ClientId>,
+}
+
+impl BlockedClients {
+    pub fn new(clients: impl IntoIterator<Item = ClientId>) -> Self {
+        Self { clients: clients.into_iter().collect() }
+    }
+}
+
+impl Rule for BlockedClients {
+    fn name(&self) -> &'static str {
+        "blocked_client"
+    }
+
+    fn check(&self, _activity: &ClientActivity, transaction: &Transaction) -> Result<(), String> {
+        if self.clients.contains(&transaction.client_id) {
+            return Err(format!("client {} is blocked", transaction.client_id));
+        }
+        Ok(())
+    }
+}
+
+pub struct MaxDeposit {
+    max: f32,
+}
+
+impl MaxDeposit {
+    pub fn new(max: f32) -> Self {
+        Self { max }
+    }
+}
+
+impl Rule for MaxDeposit {
+    fn name(&self) -> &'static str {
+        "max_deposit"
+    }
+
+    fn check(&self, _activity: &ClientActivity, transaction: &Transaction) -> Result<(), String> {
+        match (&transaction.kind, transaction.amount) {
+            (TransactionKind::Deposit, Some(amount)) if amount > self.max => {
+                Err(format!("deposit of {} exceeds max deposit {}", amount, self.max))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+// DailyWithdrawalLimit caps the sum of withdrawals of a client per UTC day.
+// Withdrawals without timestamp are only checked against the limit on their own.
+pub struct DailyWithdrawalLimit {
+    limit: f32,
+}
+
+impl DailyWithdrawalLimit {
+    pub fn new(limit: f32) -> Self {
+        Self { limit }
+    }
+}
+
+impl Rule for DailyWithdrawalLimit {
+    fn name(&self) -> &'static str {
+        "daily_withdrawal_limit"
+    }
+
+    fn check(&self, activity: &ClientActivity, transaction: &Transaction) -> Result<(), String> {
+        if transaction.kind != TransactionKind::Withdrawal {
+            return Ok(());
+        }
+        let amount = transaction.amount.unwrap_or_default();
+        let withdrawn = match transaction.timestamp {
+            Some(timestamp) => activity.withdrawn_on_day(timestamp / DAY_SECS),
+            None => 0.0,
+        };
+        if withdrawn + amount > self.limit {
+            return Err(format!("withdrawal of {} exceeds daily limit {}, already withdrawn {}", amount, self.limit, withdrawn));
+        }
+        Ok(())
+    }
+
+    fn retention(&self) -> u64 {
+        DAY_SECS
+    }
+}
+
+// VelocityLimit caps the number of transactions of a client within a sliding window of seconds.
+pub struct VelocityLimit {
+    max_transactions: usize,
+    window: u64,
+}
+
+impl VelocityLimit {
+    pub fn new(max_transactions: usize, window: u64) -> Self {
+        Self { max_transactions, window }
+    }
+}
+
+impl Rule for VelocityLimit {
+    fn name(&self) -> &'static str {
+        "velocity_limit"
+    }
+
+    fn check(&self, activity: &ClientActivity, transaction: &Transaction) -> Result<(), String> {
+        let timestamp = match transaction.timestamp {
+            Some(timestamp) => timestamp,
+            None => return Ok(()),
+        };
+        let count = activity.count_since((timestamp + 1).saturating_sub(self.window));
+        if count >= self.max_transactions {
+            return Err(format!("{} transactions within {} seconds", count, self.window));
+        }
+        Ok(())
+    }
+
+    fn retention(&self) -> u64 {
+        self.window
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VelocityConfig {
+    pub max_transactions: usize,
+    pub window_secs: u64,
+}
+
+// RulesConfig is the toml representation of the rules, e.g.
+//   blocked_clients = [3, 4]
+//   max_deposit = 10000.0
+//   daily_withdrawal_limit = 500.0
+//   [velocity]
+//   max_transactions = 10
+//   window_secs = 60
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub blocked_clients: Vec<ClientId>,
+    pub max_deposit: Option<f32>,
+    pub daily_withdrawal_limit: Option<f32>,
+    pub velocity: Option<VelocityConfig>,
+}
+
+// RuleSet evaluates its rules against the activity of the transaction client.
+// It is shared by all engine workers, so client activity survives re-sharding.
+#[derive(Default)]
+pub struct RuleSet {
+    rules: Vec<Box<dyn Rule>>,
+    activity: Mutex<HashMap<ClientId, ClientActivity>>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Box<dyn Rule>>) -> Self {
+        Self { rules, activity: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn from_config(config: RulesConfig) -> Self {
+        let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+        if !config.blocked_clients.is_empty() {
+            rules.push(Box::new(BlockedClients::new(config.blocked_clients)));
+        }
+        if let Some(max) = config.max_deposit {
+            rules.push(Box::new(MaxDeposit::new(max)));
+        }
+        if let Some(limit) = config.daily_withdrawal_limit {
+            rules.push(Box::new(DailyWithdrawalLimit::new(limit)));
+        }
+        if let Some(velocity) = config.velocity {
+            rules.push(Box::new(VelocityLimit::new(velocity.max_transactions, velocity.window_secs)));
+        }
+        Self::new(rules)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        let config: RulesConfig = toml::from_str(&content)
+            .map_err(|e| Error::new(ErrorKind::ConfigError(format!("invalid rules file {}: {}", path.display(), e))))?;
+        Ok(Self::from_config(config))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Returns a RuleViolation error tagged with the first rule the transaction breaks.
+    pub fn evaluate(&self, transaction: &Transaction) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let activity = self.activity.lock().expect("rules activity lock poisoned");
+        let empty = ClientActivity::default();
+        let client_activity = activity.get(&transaction.client_id).unwrap_or(&empty);
+
+        for rule in self.rules.iter() {
+            if let Err(reason) = rule.check(client_activity, transaction) {
+                tracing::error!("Rule {} rejected transaction {}: {}", rule.name(), transaction.id, reason);
+                return Err(Error::new(ErrorKind::RuleViolation(rule.name().to_string(), reason)));
+            }
+        }
+        Ok(())
+    }
+
+    // Records an applied transaction in the client activity.
+    pub fn record(&self, transaction: &Transaction) {
+        let timestamp = match transaction.timestamp {
+            Some(timestamp) if !self.is_empty() => timestamp,
+            _ => return,
+        };
+        let retention = self.rules.iter().map(|r| r.retention()).max().unwrap_or_default();
+        let mut activity = self.activity.lock().expect("rules activity lock poisoned");
+        let client_activity = activity.entry(transaction.client_id).or_default();
+        client_activity.record(transaction);
+        client_activity.prune(timestamp.saturating_sub(retention));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::{ids::ClientId, error::ErrorKind, transactions::{Transaction, TransactionKind}};
+
+    use super::{BlockedClients, ClientActivity, DailyWithdrawalLimit, MaxDeposit, Rule, RuleSet, RulesConfig, VelocityConfig, VelocityLimit};
+
+    fn withdrawal(id: u64, amount: f32, timestamp: u64) -> Transaction {
+        Transaction::new(TransactionKind::Withdrawal, 1, id, Some(amount)).with_timestamp(timestamp)
+    }
+
+    #[test]
+    fn test_blocked_clients() {
+        let rule = BlockedClients::new(vec![ClientId(2)]);
+        let activity = ClientActivity::default();
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Deposit, 1, 1, Some(1.0))).is_ok());
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Deposit, 2, 2, Some(1.0))).is_err());
+    }
+
+    #[test]
+    fn test_max_deposit() {
+        let rule = MaxDeposit::new(100.0);
+        let activity = ClientActivity::default();
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Deposit, 1, 1, Some(100.0))).is_ok());
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Deposit, 1, 2, Some(100.5))).is_err());
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(500.0))).is_ok());
+    }
+
+    #[test]
+    fn test_daily_withdrawal_limit() {
+        let rule = DailyWithdrawalLimit::new(100.0);
+        let mut activity = ClientActivity::default();
+        activity.record(&withdrawal(1, 60.0, 1000));
+
+        assert!(rule.check(&activity, &withdrawal(2, 40.0, 2000)).is_ok());
+        assert!(rule.check(&activity, &withdrawal(2, 41.0, 2000)).is_err());
+        // Next day starts with a fresh limit.
+        assert!(rule.check(&activity, &withdrawal(2, 90.0, 86400 + 10)).is_ok());
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(101.0))).is_err());
+    }
+
+    #[test]
+    fn test_velocity_limit() {
+        let rule = VelocityLimit::new(2, 60);
+        let mut activity = ClientActivity::default();
+        activity.record(&withdrawal(1, 1.0, 1000));
+        activity.record(&withdrawal(2, 1.0, 1030));
+
+        assert!(rule.check(&activity, &withdrawal(3, 1.0, 1059)).is_err());
+        assert!(rule.check(&activity, &withdrawal(3, 1.0, 1060)).is_ok());
+        assert!(rule.check(&activity, &Transaction::new(TransactionKind::Withdrawal, 1, 3, Some(1.0))).is_ok());
+    }
+
+    #[test]
+    fn test_rule_set_from_config() {
+        let config: RulesConfig = toml::from_str(r#"
+            blocked_clients = [3]
+            max_deposit = 1000.0
+            daily_withdrawal_limit = 100.0
+            [velocity]
+            max_transactions = 5
+            window_secs = 60
+        "#).unwrap();
+        assert_eq!(config, RulesConfig {
+            blocked_clients: vec![ClientId(3)],
+            max_deposit: Some(1000.0),
+            daily_withdrawal_limit: Some(100.0),
+            velocity: Some(VelocityConfig { max_transactions: 5, window_secs: 60 }),
+        });
+        assert!(toml::from_str::<RulesConfig>("max_withdrawal = 1.0").is_err());
+
+        let rules = RuleSet::from_config(config);
+        rules.record(&withdrawal(1, 80.0, 1000));
+
+        let err = rules.evaluate(&withdrawal(2, 30.0, 1010)).unwrap_err();
+        match &*err.kind {
+            ErrorKind::RuleViolation(rule, _) => assert_eq!(rule, "daily_withdrawal_limit"),
+            _ => panic!("unexpected error {}", err),
+        }
+
+        let err = rules.evaluate(&Transaction::new(TransactionKind::Deposit, 3, 3, Some(1.0))).unwrap_err();
+        match &*err.kind {
+            ErrorKind::RuleViolation(rule, _) => assert_eq!(rule, "blocked_client"),
+            _ => panic!("unexpected error {}", err),
+        }
+
+        assert!(rules.evaluate(&withdrawal(2, 20.0, 1010)).is_ok());
+    }
+}
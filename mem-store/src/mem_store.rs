@@ -1,13 +1,21 @@
-use models::{transactions::{Transaction, TransactionKind}, account::Account, error::{Error, ErrorKind}, store::Store};
-use std::{collections::HashMap, sync::Arc, pin::Pin};
+use models::{transactions::{Transaction, TransactionKind}, account::Account, ids::{ClientId, TransactionId}, outcome::Outcome, error::{Error, ErrorKind}, stats::{AccountStats, Activity}, store::Store};
+use std::{collections::{HashMap, HashSet}, sync::Arc, pin::Pin};
 
 use async_trait::async_trait;
 use tokio::sync::RwLock;
 
 #[derive(Debug, Clone)]
 pub struct MemStore {
-    transactions: Arc<RwLock<HashMap<u32, Transaction>>>,
-    accounts: Arc<RwLock<HashMap<u16, Account>>>,
+    transactions: Arc<RwLock<HashMap<TransactionId, Transaction>>>,
+    accounts: Arc<RwLock<HashMap<ClientId, Account>>>,
+    // Ids of transactions currently under dispute, kept to avoid scanning all transactions.
+    disputed: Arc<RwLock<HashSet<TransactionId>>>,
+    // Transactions held for review, parked until released or cancelled.
+    held: Arc<RwLock<HashMap<TransactionId, Transaction>>>,
+    outcomes: Arc<RwLock<Vec<Outcome>>>,
+    stats: Arc<RwLock<HashMap<ClientId, AccountStats>>>,
+    // Transactions which crashed an engine worker.
+    dead_letters: Arc<RwLock<Vec<Transaction>>>,
 }
 
 impl Default for MemStore {
@@ -15,6 +23,11 @@ impl Default for MemStore {
         Self {
             transactions: Arc::new(RwLock::new(HashMap::new())),
             accounts: Arc::new(RwLock::new(HashMap::new())),
+            disputed: Arc::new(RwLock::new(HashSet::new())),
+            held: Arc::new(RwLock::new(HashMap::new())),
+            outcomes: Arc::new(RwLock::new(Vec::new())),
+            stats: Arc::new(RwLock::new(HashMap::new())),
+            dead_letters: Arc::new(RwLock::new(Vec::new())),
         }
     }
 }
@@ -29,11 +42,18 @@ impl Store for MemStore {
                 .transactions
                 .write().await;
 
+            if self.held.read().await.contains_key(&transaction.id) {
+                return Err(Error::new(ErrorKind::StoreError("Transaction with transaction id exists.".to_string())));
+            }
+
             match result.entry(transaction.id) {
                 std::collections::hash_map::Entry::Occupied(_) => {
                     Err(Error::new(ErrorKind::StoreError("Transaction with transaction id exists.".to_string())))
                 },
                 std::collections::hash_map::Entry::Vacant(_) => {
+                    if transaction.under_dispute {
+                        self.disputed.write().await.insert(transaction.id);
+                    }
                     result.insert(transaction.id, transaction.clone());
                     Ok(transaction)
                 }
@@ -43,7 +63,7 @@ impl Store for MemStore {
         }
     }
 
-    async fn get_transaction(&self, id: u32) -> Result<Transaction, Error> {
+    async fn get_transaction(&self, id: TransactionId) -> Result<Transaction, Error> {
         tracing::debug!("Getting transaction {}", id);
         let result = self
             .transactions
@@ -56,27 +76,97 @@ impl Store for MemStore {
         a
     }
 
-    async fn delete_transaction(&self, id: u32) -> Result<(), Error> {
+    async fn delete_transaction(&self, id: TransactionId) -> Result<(), Error> {
         tracing::debug!("Deleting transaction: {:?}", id);
         let mut result = self.transactions
             .write().await;
 
         result.remove(&id);
+        self.disputed.write().await.remove(&id);
         Ok(())
     }
 
-    async fn set_transaction_under_dispute(&self, id: u32, under_dispute: bool) -> Result<(), Error> {
+    async fn set_transaction_under_dispute(&self, id: TransactionId, under_dispute: bool) -> Result<(), Error> {
         tracing::debug!("Setting transaction with id {} under dispute to {}", id, under_dispute);
         let mut result = self.transactions
             .write().await;
 
         if let Some(transaction) = result.get_mut(&id) {
             transaction.set_under_dispute(under_dispute);
+            let mut disputed = self.disputed.write().await;
+            if under_dispute {
+                disputed.insert(id);
+            } else {
+                disputed.remove(&id);
+            }
         }
         Ok(())
     }
 
-    async fn get_account(&self, id: u16) -> Result<Account, Error> {
+    async fn set_transaction_disputed_at(&self, id: TransactionId, disputed_at: Option<u64>) -> Result<(), Error> {
+        tracing::debug!("Setting transaction with id {} disputed at to {:?}", id, disputed_at);
+        let mut result = self.transactions
+            .write().await;
+
+        if let Some(transaction) = result.get_mut(&id) {
+            transaction.set_disputed_at(disputed_at);
+        }
+        Ok(())
+    }
+
+    async fn hold_transaction(&self, transaction: Transaction) -> Result<(), Error> {
+        tracing::debug!("Holding transaction: {:?}", transaction);
+        let mut result = self.held
+            .write().await;
+
+        match result.entry(transaction.id) {
+            std::collections::hash_map::Entry::Occupied(_) => {
+                Err(Error::new(ErrorKind::StoreError("Held transaction with transaction id exists.".to_string())))
+            },
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(transaction);
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_held_transaction(&self, id: TransactionId) -> Result<Transaction, Error> {
+        tracing::debug!("Getting held transaction {}", id);
+        let result = self.held
+            .read().await;
+
+        match result.get(&id) {
+            Some(t) => Ok(t.clone()),
+            None => Err(Error::new(ErrorKind::StoreError("Held transaction with transaction Id does not exist.".to_string()))),
+        }
+    }
+
+    async fn remove_held_transaction(&self, id: TransactionId) -> Result<(), Error> {
+        tracing::debug!("Removing held transaction: {}", id);
+        self.held.write().await.remove(&id);
+        Ok(())
+    }
+
+    async fn get_held_transactions(&self) -> Result<Vec<Transaction>, Error> {
+        tracing::debug!("Getting held transactions");
+        Ok(self.held.read().await.values().cloned().collect())
+    }
+
+    async fn get_disputed_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, Error> {
+        tracing::debug!("Getting disputed transactions for client {}", client_id);
+        let result = self
+            .transactions
+            .read().await;
+        let disputed = self.disputed.read().await;
+
+        Ok(disputed.iter()
+            .filter_map(|id| result.get(id))
+            .filter(|t| t.client_id == client_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_account(&self, id: ClientId) -> Result<Account, Error> {
         tracing::debug!("Getting account: {}", id);
         let result = self
             .accounts
@@ -87,7 +177,7 @@ impl Store for MemStore {
             Some(a) => {
                 return Ok(a.clone())
             },
-            None => return Ok(Account::new(id)),
+            None => return Ok(Account::new(id.0)),
         }
     }
 
@@ -107,7 +197,50 @@ impl Store for MemStore {
             .accounts
             .read().await;
 
-        Ok(Box::pin(futures::stream::iter(result.values().cloned().collect::<Vec<_>>())))
+        // Sorted by client, so the order does not depend on the map.
+        let mut accounts = result.values().cloned().collect::<Vec<_>>();
+        accounts.sort_by_key(|a| a.client);
+        Ok(Box::pin(futures::stream::iter(accounts)))
+    }
+
+    async fn add_outcome(&self, outcome: Outcome) -> Result<(), Error> {
+        tracing::debug!("Adding outcome: {:?}", outcome);
+        self.outcomes.write().await.push(outcome);
+        Ok(())
+    }
+
+    async fn get_all_outcomes(&self) -> Result<Pin<Box<dyn futures::Stream<Item = Outcome> + Send>>, Error> {
+        tracing::debug!("getting all outcomes");
+        let result = self
+            .outcomes
+            .read().await;
+
+        Ok(Box::pin(futures::stream::iter(result.clone())))
+    }
+
+    async fn record_activity(&self, activity: Activity) -> Result<(), Error> {
+        tracing::debug!("Recording activity: {:?}", activity);
+        self.stats.write().await
+            .entry(activity.client)
+            .or_insert_with(|| AccountStats::new(activity.client))
+            .record(&activity);
+        Ok(())
+    }
+
+    async fn get_all_stats(&self) -> Result<Pin<Box<dyn futures::Stream<Item = AccountStats> + Send>>, Error> {
+        let mut stats = self.stats.read().await.values().cloned().collect::<Vec<_>>();
+        stats.sort_by_key(|s| s.client);
+        Ok(Box::pin(futures::stream::iter(stats)))
+    }
+
+    async fn add_dead_letter(&self, transaction: Transaction) -> Result<(), Error> {
+        tracing::debug!("Adding dead letter transaction: {}", transaction.id);
+        self.dead_letters.write().await.push(transaction);
+        Ok(())
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<Transaction>, Error> {
+        Ok(self.dead_letters.read().await.clone())
     }
 }
 
@@ -115,7 +248,8 @@ impl Store for MemStore {
 mod tests {
     use std::sync::Arc;
 
-    use models::{transactions::{TransactionKind, Transaction}, logger::create_span, store::Store, error::{ErrorKind, Error}, account::Account};
+    use futures::StreamExt;
+    use models::{transactions::{TransactionKind, Transaction}, ids::{ClientId, TransactionId}, logger::create_span, store::Store, error::{ErrorKind, Error}, account::Account};
 
     use super::MemStore;
 
@@ -212,5 +346,36 @@ mod tests {
         assert!(result.is_ok());
         let result = store.get_account(account.client).await;
         assert!(result.is_ok());
+
+        for client in [7, 3, 5] {
+            store.update_account(&Account::load(client, 1.0, 0.0, false)).await.unwrap();
+        }
+        let clients = store.get_all_accounts().await.unwrap().map(|a| a.client.0).collect::<Vec<_>>().await;
+        assert_eq!(clients, vec![1, 3, 5, 7]);
+    }
+
+    #[test]
+    fn test_disputed_transactions() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let store = MemStore::default();
+        rt.block_on(run_disputed_transactions_test(store))
+    }
+
+    async fn run_disputed_transactions_test(store: MemStore) {
+        store.add_transaction(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))).await.unwrap();
+        store.add_transaction(Transaction::new(TransactionKind::Deposit, 1, 2, Some(10.0))).await.unwrap();
+        store.add_transaction(Transaction::new(TransactionKind::Deposit, 2, 3, Some(10.0))).await.unwrap();
+
+        store.set_transaction_under_dispute(TransactionId(2), true).await.unwrap();
+        store.set_transaction_disputed_at(TransactionId(2), Some(100)).await.unwrap();
+        store.set_transaction_under_dispute(TransactionId(3), true).await.unwrap();
+
+        let disputed = store.get_disputed_transactions(ClientId(1)).await.unwrap();
+        assert_eq!(disputed.len(), 1);
+        assert_eq!(disputed[0].id, TransactionId(2));
+        assert_eq!(disputed[0].disputed_at, Some(100));
+
+        store.set_transaction_under_dispute(TransactionId(2), false).await.unwrap();
+        assert!(store.get_disputed_transactions(ClientId(1)).await.unwrap().is_empty());
     }
 }
\ No newline at end of file
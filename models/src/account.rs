@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::ids::{ClientId, RawClientId, TransactionId};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Account {
-    pub client: u16,
+    pub client: ClientId,
     pub available: f32,
     pub held: f32,
     pub total: f32,
@@ -10,9 +12,9 @@ pub struct Account {
 }
 
 impl Account {
-    pub const fn new(client: u16) -> Self {
+    pub const fn new(client: RawClientId) -> Self {
         Self {
-            client,
+            client: ClientId(client),
             available: 0.0,
             held: 0.0,
             total: 0.0,
@@ -20,9 +22,9 @@ impl Account {
         }
     }
 
-    pub fn load(client: u16, available: f32, held: f32, locked: bool) -> Self {
+    pub fn load(client: RawClientId, available: f32, held: f32, locked: bool) -> Self {
         Self {
-            client,
+            client: ClientId(client),
             available,
             held,
             total: available + held,
@@ -37,7 +39,16 @@ impl Account {
     }
 }
 
-fn truncate(num: &f32) -> f32 {
+// AccountChange is the account of a client right after a transaction changed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountChange {
+    // Input sequence of the transaction which changed the account.
+    pub sequence: Option<u64>,
+    pub tx: TransactionId,
+    pub account: Account,
+}
+
+pub(crate) fn truncate(num: &f32) -> f32 {
     let s = format!("{:.4}", num);
     s.parse::<f32>().expect("truncate failed")
 }
\ No newline at end of file
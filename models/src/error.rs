@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::transactions::Transaction;
+use crate::{ids::{ClientId, TransactionId}, transactions::Transaction};
 
 #[derive(Clone, Debug)]
 pub struct Error {
@@ -57,10 +57,16 @@ pub enum ErrorKind {
     JoinError(tokio::task::JoinError),
     StoreError(String),
     EngineError(String),
-    WrongClientError(u32, u16, u16),
+    ConfigError(String),
+    WrongClientError(TransactionId, ClientId, ClientId),
     InsufficientAvailableFunds,
-    DoubleDispute(u32),
-    WrongTransactionRef(u32),
+    DoubleDispute(TransactionId),
+    DisputeWindowExpired(TransactionId),
+    WrongTransactionRef(TransactionId),
+    RuleViolation(String, String),
+    FraudRejected(String, String),
+    QueueFull(u16),
+    TenantError(String, String),
     Unknown(String),
 }
 
@@ -74,6 +80,7 @@ impl std::fmt::Display for ErrorKind {
             ErrorKind::JoinError(err) => write!(f, "{}", err),
             ErrorKind::StoreError(msg) => write!(f, "Store error {}", msg),
             ErrorKind::EngineError(msg) => write!(f, "Engine error {}", msg),
+            ErrorKind::ConfigError(msg) => write!(f, "Config error {}", msg),
             ErrorKind::WrongClientError(txn_id, client_id, wrong_id) => {
                 write!(f, "Wrong client_id in transaction: {}, expected: {}, got: {}", txn_id, client_id, wrong_id)
             },
@@ -81,9 +88,20 @@ impl std::fmt::Display for ErrorKind {
             ErrorKind::DoubleDispute(txn_id) => {
                 write!(f, "Double dispute for transaction: {}", txn_id)
             },
+            ErrorKind::DisputeWindowExpired(txn_id) => {
+                write!(f, "Dispute window expired for transaction: {}", txn_id)
+            },
             ErrorKind::WrongTransactionRef(txn_id) => {
                 write!(f, "Wrong reference for transaction: {}", txn_id)
             },
+            ErrorKind::RuleViolation(rule, msg) => {
+                write!(f, "Rule {} rejected transaction: {}", rule, msg)
+            },
+            ErrorKind::FraudRejected(hook, msg) => {
+                write!(f, "Fraud hook {} rejected transaction: {}", hook, msg)
+            },
+            ErrorKind::QueueFull(worker) => write!(f, "Queue full for engine worker {}", worker),
+            ErrorKind::TenantError(tenant, msg) => write!(f, "Tenant {} error {}", tenant, msg),
             ErrorKind::Unknown(msg) => write!(f, "Unknown error {}", msg),
         }
     }
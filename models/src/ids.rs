@@ -0,0 +1,111 @@
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+// Underlying integer types of the ids, widen here to support larger id spaces.
+pub type RawClientId = u32;
+pub type RawTransactionId = u64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct ClientId(pub RawClientId);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct TransactionId(pub RawTransactionId);
+
+impl ClientId {
+    // Returns the worker shard of the client for the given number of shards.
+    pub fn shard(&self, shards: u16) -> u16 {
+        (self.0 % RawClientId::from(shards)) as u16
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<RawClientId> for ClientId {
+    fn from(id: RawClientId) -> Self {
+        ClientId(id)
+    }
+}
+
+impl From<RawTransactionId> for TransactionId {
+    fn from(id: RawTransactionId) -> Self {
+        TransactionId(id)
+    }
+}
+
+// IdVisitor accepts ids given as numbers or strings and reports out of range
+// values with the id name instead of a generic parse error.
+struct IdVisitor {
+    name: &'static str,
+    max: u64,
+}
+
+impl<'de> de::Visitor<'de> for IdVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} between 0 and {}", self.name, self.max)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        if v > self.max {
+            return Err(E::custom(format!("{} {} is out of range, max {}", self.name, v, self.max)));
+        }
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        if v < 0 {
+            return Err(E::custom(format!("{} {} is out of range, min 0", self.name, v)));
+        }
+        self.visit_u64(v as u64)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<u64, E> {
+        Err(E::custom(format!("{} {} is out of range, max {}", self.name, v, self.max)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<u64, E> {
+        if v.fract() == 0.0 && (v < 0.0 || v > self.max as f64) {
+            return Err(E::custom(format!("{} {} is out of range, max {}", self.name, v, self.max)));
+        }
+        Err(E::invalid_type(de::Unexpected::Float(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        match v.trim().parse::<u128>() {
+            Ok(v) if v > u128::from(u64::MAX) => self.visit_u128(v),
+            Ok(v) => self.visit_u64(v as u64),
+            Err(_) => match v.trim().parse::<i64>() {
+                Ok(v) => self.visit_i64(v),
+                Err(_) => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = deserializer.deserialize_any(IdVisitor { name: "client id", max: RawClientId::MAX.into() })?;
+        Ok(ClientId(id as RawClientId))
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = deserializer.deserialize_any(IdVisitor { name: "transaction id", max: RawTransactionId::MAX })?;
+        Ok(TransactionId(id as RawTransactionId))
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use futures::Future;
-use tokio::{runtime::Runtime, task::JoinHandle};
+use tokio::{runtime::Runtime, sync::watch, task::JoinHandle};
 use tracing::{Span, Instrument};
 
 #[derive(Clone)]
@@ -40,6 +40,43 @@ impl SpannedRuntime {
     }
 }
 
+// CancellationToken asks long running work to stop, all clones share the same state.
+#[derive(Clone)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender: Arc::new(sender), receiver }
+    }
+
+    pub fn cancel(&self) {
+        let _ = self.sender.send(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    // cancelled completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            // The sender lives as long as any clone of the token.
+            let _ = receiver.changed().await;
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub fn get_runtime(worker_threads: usize, blocking_threads: usize, span: tracing::Span) -> Result<SpannedRuntime, std::io::Error> {
     let rt = init_runtime(worker_threads, blocking_threads)?;
     Ok(SpannedRuntime::new(rt, span))
@@ -1,6 +1,9 @@
 pub mod account;
+pub mod ids;
 pub mod transactions;
+pub mod outcome;
 pub mod error;
 pub mod infra;
 pub mod store;
+pub mod stats;
 pub mod logger;
\ No newline at end of file
@@ -8,7 +8,7 @@ pub fn create_span() -> Span {
     let span = tracing::span!(tracing::Level::ERROR, "pht", id=tracing::field::Empty);
 
     if let Some(span_id) = span.id() {
-        span.record("id",&span_id.into_u64());
+        span.record("id",span_id.into_u64());
     }
 
     span
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ids::{ClientId, TransactionId};
+use crate::transactions::{metadata_string, Transaction, TransactionKind};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeStatus {
+    Applied,
+    Rejected,
+    Held,
+    AutoResolved,
+    AutoChargedBack,
+    // The transaction crashed its engine worker and was sent to the dead letters.
+    Failed,
+}
+
+// Outcome records what the engine did with a single transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Outcome {
+    // Input sequence of the transaction which produced the outcome.
+    #[serde(rename = "seq")]
+    pub sequence: Option<u64>,
+    #[serde(rename = "type")]
+    pub kind: TransactionKind,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub status: OutcomeStatus,
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub merchant: Option<String>,
+    pub description: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    // Name of the rule or fraud hook which rejected or held the transaction, if any.
+    pub rule: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl Outcome {
+    pub fn new(transaction: &Transaction, status: OutcomeStatus, reason: Option<String>) -> Self {
+        Self {
+            sequence: transaction.sequence,
+            kind: transaction.kind.clone(),
+            client: transaction.client_id,
+            tx: transaction.id,
+            status,
+            timestamp: transaction.timestamp,
+            reference: transaction.reference.clone(),
+            merchant: transaction.merchant.clone(),
+            description: transaction.description.clone(),
+            metadata: transaction.metadata.clone(),
+            rule: None,
+            reason,
+        }
+    }
+
+    // Returns metadata encoded like Transaction::metadata_string, for flat outputs such as csv.
+    pub fn metadata_string(&self) -> Option<String> {
+        metadata_string(&self.metadata)
+    }
+}
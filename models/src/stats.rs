@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::account::{truncate, Account};
+use crate::ids::ClientId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActivityKind {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    ChargeBack,
+    Rejected,
+}
+
+// Activity is a single transaction counted in the statistics of a client,
+// disputes, resolves and chargebacks carry the amount of the disputed deposit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    pub client: ClientId,
+    pub kind: ActivityKind,
+    pub amount: f32,
+}
+
+impl Activity {
+    pub fn new(client: ClientId, kind: ActivityKind, amount: f32) -> Self {
+        Self { client, kind, amount }
+    }
+}
+
+// AccountStats aggregates the activity of a client over a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AccountStats {
+    pub client: ClientId,
+    pub deposits: u64,
+    pub deposited: f32,
+    pub withdrawals: u64,
+    pub withdrawn: f32,
+    // Disputes not resolved or charged back yet, and the amount they hold.
+    pub open_disputes: u64,
+    pub disputed: f32,
+    pub chargebacks: u64,
+    pub charged_back: f32,
+    pub rejected: u64,
+    pub rejected_amount: f32,
+}
+
+impl AccountStats {
+    pub fn new(client: ClientId) -> Self {
+        Self { client, ..Self::default() }
+    }
+
+    pub fn record(&mut self, activity: &Activity) {
+        let amount = activity.amount;
+        match activity.kind {
+            ActivityKind::Deposit => {
+                self.deposits += 1;
+                self.deposited += amount;
+            },
+            ActivityKind::Withdrawal => {
+                self.withdrawals += 1;
+                self.withdrawn += amount;
+            },
+            ActivityKind::Dispute => {
+                self.open_disputes += 1;
+                self.disputed += amount;
+            },
+            ActivityKind::Resolve => self.close_dispute(amount),
+            ActivityKind::ChargeBack => {
+                self.close_dispute(amount);
+                self.chargebacks += 1;
+                self.charged_back += amount;
+            },
+            ActivityKind::Rejected => {
+                self.rejected += 1;
+                self.rejected_amount += amount;
+            },
+        }
+    }
+
+    pub fn to_max_display_precision(&mut self) {
+        for sum in [&mut self.deposited, &mut self.withdrawn, &mut self.disputed, &mut self.charged_back, &mut self.rejected_amount] {
+            *sum = truncate(sum);
+        }
+    }
+
+    fn close_dispute(&mut self, amount: f32) {
+        self.open_disputes = self.open_disputes.saturating_sub(1);
+        self.disputed -= amount;
+    }
+}
+
+// ExtendedAccount is an account along with the statistics of its client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedAccount {
+    pub account: Account,
+    pub stats: AccountStats,
+}
+
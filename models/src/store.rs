@@ -3,16 +3,31 @@ use std::pin::Pin;
 use async_trait::async_trait;
 
 use crate::account::Account;
+use crate::ids::{ClientId, TransactionId};
+use crate::outcome::Outcome;
+use crate::stats::{AccountStats, Activity};
 use crate::transactions::Transaction;
 use crate::error::Error;
 
 #[async_trait]
 pub trait Store: Send + Sync {
     async fn add_transaction(&self, transaction: Transaction) -> Result<Transaction, Error>;
-    async fn get_transaction(&self, id: u32) -> Result<Transaction, Error>;
-    async fn delete_transaction(&self, id: u32) -> Result<(), Error>;
-    async fn set_transaction_under_dispute(&self, id: u32, under_dispute: bool) -> Result<(), Error>;
-    async fn get_account(&self, id: u16) -> Result<Account, Error>;
+    async fn get_transaction(&self, id: TransactionId) -> Result<Transaction, Error>;
+    async fn delete_transaction(&self, id: TransactionId) -> Result<(), Error>;
+    async fn set_transaction_under_dispute(&self, id: TransactionId, under_dispute: bool) -> Result<(), Error>;
+    async fn set_transaction_disputed_at(&self, id: TransactionId, disputed_at: Option<u64>) -> Result<(), Error>;
+    async fn hold_transaction(&self, transaction: Transaction) -> Result<(), Error>;
+    async fn get_held_transaction(&self, id: TransactionId) -> Result<Transaction, Error>;
+    async fn remove_held_transaction(&self, id: TransactionId) -> Result<(), Error>;
+    async fn get_held_transactions(&self) -> Result<Vec<Transaction>, Error>;
+    async fn get_disputed_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, Error>;
+    async fn get_account(&self, id: ClientId) -> Result<Account, Error>;
     async fn update_account(&self, account: &Account) -> Result<(), Error>;
     async fn get_all_accounts(&self) -> Result<Pin<Box<dyn futures::Stream<Item = Account> + Send>>, Error>;
+    async fn add_outcome(&self, outcome: Outcome) -> Result<(), Error>;
+    async fn get_all_outcomes(&self) -> Result<Pin<Box<dyn futures::Stream<Item = Outcome> + Send>>, Error>;
+    async fn record_activity(&self, activity: Activity) -> Result<(), Error>;
+    async fn get_all_stats(&self) -> Result<Pin<Box<dyn futures::Stream<Item = AccountStats> + Send>>, Error>;
+    async fn add_dead_letter(&self, transaction: Transaction) -> Result<(), Error>;
+    async fn get_dead_letters(&self) -> Result<Vec<Transaction>, Error>;
 }
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::ids::{ClientId, RawClientId, RawTransactionId, TransactionId};
+
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -9,6 +13,30 @@ pub enum TransactionKind {
     Dispute,
     Resolve,
     ChargeBack,
+    // Admin operations on a transaction held for review.
+    Release,
+    Cancel,
+}
+
+impl TransactionKind {
+    // Returns the name of the kind as read from and written to input.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionKind::Deposit => "deposit",
+            TransactionKind::Withdrawal => "withdrawal",
+            TransactionKind::Dispute => "dispute",
+            TransactionKind::Resolve => "resolve",
+            TransactionKind::ChargeBack => "chargeback",
+            TransactionKind::Release => "release",
+            TransactionKind::Cancel => "cancel",
+        }
+    }
+
+    // Admin operations skip rules and fraud hooks, they are only posted
+    // through the publisher and never read from input.
+    pub fn is_admin(&self) -> bool {
+        matches!(self, TransactionKind::Release | TransactionKind::Cancel)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -16,39 +44,103 @@ pub struct Transaction {
     #[serde(rename = "type")]
     pub kind: TransactionKind,
     #[serde(rename = "client")]
-    pub client_id: u16,
+    pub client_id: ClientId,
     #[serde(rename = "tx")]
-    pub id: u32,
+    pub id: TransactionId,
     #[serde(default)]
     pub amount: Option<f32>,
+    // Unix timestamp in seconds, optional so four column input keeps working.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    // External reference of the transaction in the upstream system.
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub merchant: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    // Free form key/value pairs, filled by readers from non standard columns.
+    #[serde(skip)]
+    pub metadata: BTreeMap<String, String>,
     #[serde(skip)]
     pub under_dispute: bool,
+    // Timestamp of the dispute currently open on this transaction.
+    #[serde(skip)]
+    pub disputed_at: Option<u64>,
+    // Position of the transaction in the input, stamped by the publisher.
+    #[serde(skip)]
+    pub sequence: Option<u64>,
 }
 
 impl Transaction {
-    pub const fn new(kind: TransactionKind, client_id: u16, id: u32, amount: Option<f32>) -> Self {
+    pub const fn new(kind: TransactionKind, client_id: RawClientId, id: RawTransactionId, amount: Option<f32>) -> Self {
         Self {  kind,
-                client_id,
-                id,
+                client_id: ClientId(client_id),
+                id: TransactionId(id),
                 amount,
-                under_dispute: false
+                timestamp: None,
+                reference: None,
+                merchant: None,
+                description: None,
+                metadata: BTreeMap::new(),
+                under_dispute: false,
+                disputed_at: None,
+                sequence: None,
              }
     }
 
+    pub const fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    // Returns metadata encoded as key=value pairs separated by ';', None when empty.
+    pub fn metadata_string(&self) -> Option<String> {
+        metadata_string(&self.metadata)
+    }
+
     pub fn is_valid_amount(&self) -> bool {
         match self.amount {
             Some(a) => {
-                if a < 0.0 {
-                    return false;
-                } else {
-                    return true;
-                }
+                a >= 0.0
             },
-            None => return true,
+            None => true,
         }
     }
 
     pub fn set_under_dispute(&mut self, under_dispute: bool) {
         self.under_dispute = under_dispute;
     }
-}
\ No newline at end of file
+
+    pub fn set_disputed_at(&mut self, disputed_at: Option<u64>) {
+        self.disputed_at = disputed_at;
+    }
+}
+
+// metadata_string encodes metadata as key=value pairs separated by ';', None when empty.
+// '\', '=' and ';' within keys and values are escaped with a '\'.
+pub fn metadata_string(metadata: &BTreeMap<String, String>) -> Option<String> {
+    if metadata.is_empty() {
+        return None;
+    }
+    Some(metadata.iter()
+        .map(|(k, v)| format!("{}={}", escape_metadata(k), escape_metadata(v)))
+        .collect::<Vec<_>>()
+        .join(";"))
+}
+
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '=' | ';') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, sync::atomic::{AtomicU64, Ordering}, fs::{File, OpenOptions}, io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+use models::{error::{Error, ErrorKind}, transactions::Transaction};
+
+// Backpressure decides what post_txn does when the queue of a worker is full.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Backpressure {
+    // Wait until the worker frees a slot.
+    #[default]
+    Block,
+    // Return a QueueFull error, the caller decides to retry or drop.
+    FailFast,
+    // Append to a spill file per worker in the given directory, which is fed
+    // back to the worker as it frees slots.
+    SpillToDisk(PathBuf),
+}
+
+// QueueMetrics describes the queue of an engine worker.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueMetrics {
+    pub worker: u16,
+    pub capacity: usize,
+    // Transactions posted to the worker and not processed yet, spilled ones included.
+    pub depth: u64,
+    pub spilled: u64,
+    pub max_depth: u64,
+    // Number of posts which found the queue full.
+    pub full_count: u64,
+}
+
+// Distinguishes spill files of workers spawned by concurrent publishers.
+static SPILL_ID: AtomicU64 = AtomicU64::new(0);
+
+// SpillQueue is a FIFO of transactions kept in a file as json lines.
+pub(crate) struct SpillQueue {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    len: u64,
+}
+
+impl SpillQueue {
+    pub(crate) fn create(dir: &Path, worker: u16) -> Result<Self, Error> {
+        std::fs::create_dir_all(dir)?;
+        let id = SPILL_ID.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("worker-{}-{}-{}.spill", worker, std::process::id(), id));
+        let writer = OpenOptions::new().create(true).write(true).truncate(true).open(&path)?;
+        let reader = File::open(&path)?;
+        Ok(Self { path, writer: BufWriter::new(writer), reader: BufReader::new(reader), len: 0 })
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn push(&mut self, transaction: &Transaction) -> Result<(), Error> {
+        // Metadata and sequence are not serialized with the transaction, so they are spilled alongside.
+        let line = serde_json::to_string(&(transaction, &transaction.metadata, transaction.sequence)).map_err(spill_error)?;
+        writeln!(self.writer, "{}", line)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn pop(&mut self) -> Result<Option<Transaction>, Error> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.writer.flush()?;
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        let (mut transaction, metadata, sequence): (Transaction, BTreeMap<String, String>, Option<u64>) = serde_json::from_str(&line).map_err(spill_error)?;
+        transaction.metadata = metadata;
+        transaction.sequence = sequence;
+        self.len -= 1;
+        if self.len == 0 {
+            self.truncate()?;
+        }
+        Ok(Some(transaction))
+    }
+
+    // truncate reclaims the disk space once the queue is drained.
+    fn truncate(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+impl Drop for SpillQueue {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("Failed to remove spill file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn spill_error(err: serde_json::Error) -> Error {
+    Error::new(ErrorKind::IO(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use models::transactions::{Transaction, TransactionKind};
+
+    use super::SpillQueue;
+
+    #[test]
+    fn test_spill_queue() {
+        let dir = std::env::temp_dir().join(format!("spill-test-{}", std::process::id()));
+        let mut queue = SpillQueue::create(&dir, 3).unwrap();
+        let mut first = Transaction::new(TransactionKind::Deposit, 1, 1, Some(1.5)).with_metadata("channel", "web");
+        first.sequence = Some(7);
+        let second = Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(10);
+
+        queue.push(&first).unwrap();
+        queue.push(&second).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().unwrap(), Some(first.clone()));
+        assert_eq!(queue.pop().unwrap(), Some(second));
+        assert_eq!(queue.pop().unwrap(), None);
+
+        // The queue is reusable once drained.
+        queue.push(&first).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some(first));
+        let path = queue.path.clone();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        drop(queue);
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -1 +1,9 @@
-pub mod publish;
\ No newline at end of file
+pub mod backpressure;
+pub mod priority;
+pub mod progress;
+pub mod report;
+pub mod publish;
+pub mod scaling;
+pub mod sharding;
+mod supervisor;
+pub mod tenant;
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use models::{ids::TransactionId, transactions::{Transaction, TransactionKind}};
+use tokio::sync::mpsc::Receiver;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lane {
+    High,
+    Bulk,
+}
+
+// PriorityLanes maps transaction kinds to the lane they are queued on.
+// High lane transactions of a worker are processed ahead of its queued bulk ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriorityLanes {
+    high: Vec<TransactionKind>,
+}
+
+impl PriorityLanes {
+    pub fn new(high: Vec<TransactionKind>) -> Self {
+        Self { high }
+    }
+
+    pub fn lane(&self, kind: &TransactionKind) -> Lane {
+        if self.high.contains(kind) {
+            Lane::High
+        } else {
+            Lane::Bulk
+        }
+    }
+}
+
+// Disputes, chargebacks and admin operations go first by default.
+impl Default for PriorityLanes {
+    fn default() -> Self {
+        Self::new(vec![
+            TransactionKind::Dispute,
+            TransactionKind::Resolve,
+            TransactionKind::ChargeBack,
+            TransactionKind::Release,
+            TransactionKind::Cancel,
+        ])
+    }
+}
+
+// BulkIds counts the transactions queued on the bulk lane of a worker by id.
+// A high lane transaction referencing one of them is queued behind it instead,
+// so a dispute never overtakes the deposit it references.
+#[derive(Debug, Default)]
+pub(crate) struct BulkIds {
+    ids: Mutex<HashMap<TransactionId, u32>>,
+}
+
+impl BulkIds {
+    pub(crate) fn contains(&self, id: TransactionId) -> bool {
+        self.ids.lock().expect("bulk ids lock poisoned").contains_key(&id)
+    }
+
+    pub(crate) fn add(&self, id: TransactionId) {
+        *self.ids.lock().expect("bulk ids lock poisoned").entry(id).or_default() += 1;
+    }
+
+    pub(crate) fn remove(&self, id: TransactionId) {
+        let mut ids = self.ids.lock().expect("bulk ids lock poisoned");
+        if let Some(count) = ids.get_mut(&id) {
+            *count -= 1;
+            if *count == 0 {
+                ids.remove(&id);
+            }
+        }
+    }
+}
+
+// Lanes is the receiving side of an engine worker.
+pub(crate) struct Lanes {
+    pub(crate) high: Receiver<Transaction>,
+    pub(crate) bulk: Receiver<Transaction>,
+    pub(crate) bulk_ids: Option<Arc<BulkIds>>,
+}
+
+impl Lanes {
+    // recv returns the next high lane transaction if any, else the next bulk one.
+    pub(crate) async fn recv(&mut self) -> Option<Transaction> {
+        tokio::select! {
+            biased;
+            Some(transaction) = self.high.recv() => Some(transaction),
+            Some(transaction) = self.bulk.recv() => {
+                if let Some(bulk_ids) = &self.bulk_ids {
+                    bulk_ids.remove(transaction.id);
+                }
+                Some(transaction)
+            },
+            else => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use models::{ids::TransactionId, logger::create_span, transactions::{Transaction, TransactionKind}};
+
+    use super::{BulkIds, Lane, Lanes, PriorityLanes};
+
+    #[test]
+    fn test_priority_lanes() {
+        let lanes = PriorityLanes::default();
+        assert_eq!(lanes.lane(&TransactionKind::ChargeBack), Lane::High);
+        assert_eq!(lanes.lane(&TransactionKind::Deposit), Lane::Bulk);
+        assert_eq!(PriorityLanes::new(vec![]).lane(&TransactionKind::Dispute), Lane::Bulk);
+    }
+
+    #[test]
+    fn test_lanes_recv() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        rt.block_on(async {
+            let (high, high_rx) = tokio::sync::mpsc::channel(10);
+            let (bulk, bulk_rx) = tokio::sync::mpsc::channel(10);
+            let bulk_ids = Arc::new(BulkIds::default());
+            let mut lanes = Lanes { high: high_rx, bulk: bulk_rx, bulk_ids: Some(bulk_ids.clone()) };
+
+            bulk_ids.add(TransactionId(1));
+            bulk.send(Transaction::new(TransactionKind::Deposit, 1, 1, Some(1.0))).await.unwrap();
+            high.send(Transaction::new(TransactionKind::Dispute, 1, 2, None)).await.unwrap();
+            drop(high);
+            drop(bulk);
+
+            assert_eq!(lanes.recv().await.unwrap().id, TransactionId(2));
+            assert!(bulk_ids.contains(TransactionId(1)));
+            assert_eq!(lanes.recv().await.unwrap().id, TransactionId(1));
+            assert!(!bulk_ids.contains(TransactionId(1)));
+            assert!(lanes.recv().await.is_none());
+        })
+    }
+}
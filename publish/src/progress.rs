@@ -0,0 +1,95 @@
+use std::{fmt, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use models::outcome::OutcomeStatus;
+
+// ProgressCounters counts the outcomes of transactions processed by all workers
+// of a publisher. System outcomes are not counted, so processed never exceeds posted.
+#[derive(Debug, Default)]
+pub(crate) struct ProgressCounters {
+    applied: AtomicU64,
+    rejected: AtomicU64,
+    held: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl ProgressCounters {
+    pub(crate) fn record(&self, status: &OutcomeStatus) {
+        let counter = match status {
+            OutcomeStatus::Applied => &self.applied,
+            OutcomeStatus::Rejected => &self.rejected,
+            OutcomeStatus::Held => &self.held,
+            OutcomeStatus::Failed => &self.failed,
+            OutcomeStatus::AutoResolved | OutcomeStatus::AutoChargedBack => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Progress is a snapshot of the work done by a publisher.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub posted: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    pub held: u64,
+    pub failed: u64,
+    pub elapsed: Duration,
+    pub queue_depths: Vec<u64>,
+}
+
+impl Progress {
+    pub(crate) fn new(posted: u64, counters: &ProgressCounters, elapsed: Duration, queue_depths: Vec<u64>) -> Self {
+        Self {
+            posted,
+            applied: counters.applied.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            held: counters.held.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            elapsed,
+            queue_depths,
+        }
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.applied + self.rejected + self.held + self.failed
+    }
+
+    // throughput returns the processed transactions per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.processed() as f64 / secs
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "posted {} applied {} rejected {} held {} failed {} | {:.0} tx/s | queues {:?}",
+            self.posted, self.applied, self.rejected, self.held, self.failed, self.throughput(), self.queue_depths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use models::outcome::OutcomeStatus;
+
+    use super::{Progress, ProgressCounters};
+
+    #[test]
+    fn test_progress() {
+        let counters = ProgressCounters::default();
+        for status in [OutcomeStatus::Applied, OutcomeStatus::Applied, OutcomeStatus::Rejected, OutcomeStatus::Held, OutcomeStatus::AutoResolved] {
+            counters.record(&status);
+        }
+
+        // The auto resolve is not an input transaction, it is not counted.
+        let progress = Progress::new(6, &counters, Duration::from_secs(2), vec![1, 0]);
+        assert_eq!(progress.processed(), 4);
+        assert_eq!(progress.throughput(), 2.0);
+        assert_eq!(progress.to_string(), "posted 6 applied 2 rejected 1 held 1 failed 0 | 2 tx/s | queues [1, 0]");
+    }
+}
@@ -1,64 +1,963 @@
-use models::{transactions::Transaction, error::{Error, ErrorKind}, account::Account, infra::SpannedRuntime};
-use std::{collections::HashMap, sync::Arc, pin::Pin};
+use models::{transactions::{Transaction, TransactionKind}, ids::{ClientId, TransactionId}, error::{Error, ErrorKind}, account::{Account, AccountChange}, outcome::{Outcome, OutcomeStatus}, stats::{AccountStats, Activity, ActivityKind, ExtendedAccount}, store::Store, infra::SpannedRuntime};
+use std::{collections::HashMap, sync::{atomic::{AtomicU64, Ordering}, Arc}, pin::Pin, time::Instant};
 
-use engine::engine::Engine;
+use engine::{engine::Engine, dispute::DisputePolicy, fraud::FraudHooks, middleware::Middleware, rules::RuleSet};
+use futures::StreamExt;
 use mem_store::mem_store::MemStore;
-use tokio::{sync::{mpsc::Sender, Mutex}, task::JoinHandle};
+use tokio::{sync::{mpsc::{error::TrySendError, Sender, UnboundedSender}, Mutex}, task::JoinHandle};
+
+use crate::{backpressure::{Backpressure, QueueMetrics, SpillQueue}, priority::{BulkIds, Lane, Lanes, PriorityLanes}, progress::{Progress, ProgressCounters}, report::ReportQuery, scaling::{Scaling, ScalingPolicy}, sharding::{Modulo, ShardingStrategy}, supervisor::supervise};
+
+// Idle clients are evicted from the client workers map once it holds more than
+// this many clients, or twice the number of busy clients after the last eviction.
+const MIN_CLIENT_WORKERS: usize = 1024;
+
+// Posted tells what became of a transaction posted to a worker.
+enum Posted {
+    Queued,
+    // The queue was full and the fail fast policy rejected the transaction.
+    Rejected(Transaction),
+    // The queue was full and the transaction still has to be sent on the sender.
+    Blocked(Sender<Transaction>, Transaction),
+}
+
+// Staged is a transaction accepted by a publisher while the queue of its worker
+// was full. Sending it waits for the worker without borrowing the publisher.
+pub(crate) struct Staged {
+    shard: u16,
+    sender: Sender<Transaction>,
+    transaction: Transaction,
+}
+
+impl Staged {
+    // send waits for room in the queue. A transaction which could not be sent
+    // has to be unstaged from its publisher.
+    pub(crate) async fn send(&self) -> Result<(), Error> {
+        self.sender.send(self.transaction.clone()).await?;
+        Ok(())
+    }
+}
+
+// WorkerChannel is the sending side of an engine worker along with the number
+// of transactions sent to and processed by it.
+struct WorkerChannel {
+    // Bulk lane, backpressure and spilling apply to it.
+    sender: Sender<Transaction>,
+    high: Sender<Transaction>,
+    // Ids queued on the bulk lane, tracked when priority lanes are enabled.
+    bulk_ids: Option<Arc<BulkIds>>,
+    // Transactions accepted for the worker, including those still spilled to disk.
+    sent: u64,
+    processed: Arc<AtomicU64>,
+    spill: Option<SpillQueue>,
+    max_depth: u64,
+    full_count: u64,
+}
+
+impl WorkerChannel {
+    fn new(sender: Sender<Transaction>, high: Sender<Transaction>, bulk_ids: Option<Arc<BulkIds>>, processed: Arc<AtomicU64>) -> Self {
+        Self { sender, high, bulk_ids, sent: 0, processed, spill: None, max_depth: 0, full_count: 0 }
+    }
+
+    // lane returns the lane of the transaction. High lane transactions
+    // referencing a transaction still queued on the bulk lane stay behind it.
+    fn lane(&self, transaction: &Transaction, priority: &Option<PriorityLanes>) -> Lane {
+        match (priority, &self.bulk_ids) {
+            (Some(priority), Some(bulk_ids)) if priority.lane(&transaction.kind) == Lane::High && !bulk_ids.contains(transaction.id) => Lane::High,
+            _ => Lane::Bulk,
+        }
+    }
+
+    fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Acquire)
+    }
+
+    fn queued(&self) -> u64 {
+        self.sent - self.processed()
+    }
+
+    fn spilled(&self) -> u64 {
+        self.spill.as_ref().map_or(0, |spill| spill.len())
+    }
+
+    // post queues the transaction for the worker, applying the backpressure
+    // policy when the channel of its lane is full. Once transactions are spilled, the
+    // following ones are spilled too until the spill is drained, to keep their order.
+    async fn post(&mut self, shard: u16, transaction: Transaction, lane: Lane, backpressure: &Backpressure) -> Result<Posted, Error> {
+        match lane {
+            Lane::High => self.post_high(shard, transaction, backpressure).await,
+            Lane::Bulk => self.post_bulk_lane(shard, transaction, backpressure).await,
+        }
+    }
+
+    // post_high applies the same backpressure to the high lane as to the bulk lane.
+    // High lane transactions which would be spilled go to the bulk lane instead, as only it is spilled.
+    async fn post_high(&mut self, shard: u16, transaction: Transaction, backpressure: &Backpressure) -> Result<Posted, Error> {
+        let posted = match self.high.try_send(transaction) {
+            Ok(()) => Posted::Queued,
+            Err(TrySendError::Full(transaction)) => {
+                self.full_count += 1;
+                match backpressure {
+                    Backpressure::Block => Posted::Blocked(self.high.clone(), transaction),
+                    Backpressure::FailFast => {
+                        tracing::warn!("High lane full for engine worker {}, rejecting transaction {}", shard, transaction.id);
+                        return Ok(Posted::Rejected(transaction));
+                    },
+                    Backpressure::SpillToDisk(_) => return self.post_bulk_lane(shard, transaction, backpressure).await,
+                }
+            },
+            // send reports the closed channel.
+            Err(TrySendError::Closed(transaction)) => {
+                self.high.send(transaction).await?;
+                Posted::Queued
+            },
+        };
+        self.sent += 1;
+        self.max_depth = self.max_depth.max(self.queued());
+        Ok(posted)
+    }
+
+    // post_bulk_lane tracks the id of the transaction while it is queued on the bulk lane.
+    async fn post_bulk_lane(&mut self, shard: u16, transaction: Transaction, backpressure: &Backpressure) -> Result<Posted, Error> {
+        let id = transaction.id;
+        if let Some(bulk_ids) = &self.bulk_ids {
+            bulk_ids.add(id);
+        }
+        let posted = self.post_bulk(shard, transaction, backpressure).await;
+        if !matches!(posted, Ok(Posted::Queued | Posted::Blocked(..))) {
+            if let Some(bulk_ids) = &self.bulk_ids {
+                bulk_ids.remove(id);
+            }
+            return posted;
+        }
+        self.sent += 1;
+        self.max_depth = self.max_depth.max(self.queued());
+        posted
+    }
+
+    async fn post_bulk(&mut self, shard: u16, transaction: Transaction, backpressure: &Backpressure) -> Result<Posted, Error> {
+        if self.spilled() > 0 {
+            self.full_count += 1;
+            self.spill.as_mut().expect("spill queue not empty").push(&transaction)?;
+        } else {
+            match self.sender.try_send(transaction) {
+                Ok(()) => {},
+                Err(TrySendError::Full(transaction)) => {
+                    self.full_count += 1;
+                    match backpressure {
+                        Backpressure::Block => return Ok(Posted::Blocked(self.sender.clone(), transaction)),
+                        Backpressure::FailFast => {
+                            tracing::warn!("Queue full for engine worker {}, rejecting transaction {}", shard, transaction.id);
+                            return Ok(Posted::Rejected(transaction));
+                        },
+                        Backpressure::SpillToDisk(dir) => {
+                            if self.spill.is_none() {
+                                self.spill = Some(SpillQueue::create(dir, shard)?);
+                            }
+                            tracing::debug!("Queue full for engine worker {}, spilling transaction {}", shard, transaction.id);
+                            self.spill.as_mut().expect("spill queue created above").push(&transaction)?;
+                        },
+                    }
+                },
+                // send reports the closed channel.
+                Err(TrySendError::Closed(transaction)) => self.sender.send(transaction).await?,
+            }
+        }
+        Ok(Posted::Queued)
+    }
+
+    // drain_spill moves spilled transactions to the channel while it has room.
+    fn drain_spill(&mut self) -> Result<(), Error> {
+        if let Some(spill) = self.spill.as_mut() {
+            while spill.len() > 0 {
+                match self.sender.try_reserve() {
+                    Ok(permit) => permit.send(spill.pop()?.expect("spill queue not empty")),
+                    Err(_) => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // flush_spill sends all spilled transactions, waiting for the worker.
+    async fn flush_spill(&mut self) -> Result<(), Error> {
+        if let Some(spill) = self.spill.as_mut() {
+            while let Some(transaction) = spill.pop()? {
+                self.sender.send(transaction).await?;
+            }
+        }
+        Ok(())
+    }
+}
 
 pub struct Publisher {
-    client_sender_map: HashMap<u16, Sender<Transaction>>,
+    client_sender_map: HashMap<u16, WorkerChannel>,
+    // Worker of each client and the sent count of that worker after its last transaction.
+    client_workers: HashMap<ClientId, (u16, u64)>,
+    client_workers_limit: usize,
+    sharding: Box<dyn ShardingStrategy>,
     mem_store: MemStore,
     rt: Arc<SpannedRuntime>,
     worker_count: u16,
+    channel_capacity: usize,
+    backpressure: Backpressure,
+    priority: Option<PriorityLanes>,
+    scaling: Option<ScalingPolicy>,
+    posted_since_scaling: u64,
+    // Strict serial mode processes all transactions on a single worker in input order.
+    serial: bool,
+    next_sequence: u64,
+    // Sequence of the first transaction of each client, orders the account report.
+    client_sequences: HashMap<ClientId, u64>,
+    // Latest timestamp posted, open disputes are expired against it at shutdown.
+    watermark: Option<u64>,
+    counters: Arc<ProgressCounters>,
+    started: Option<Instant>,
+    dispute_policy: DisputePolicy,
+    rules: Arc<RuleSet>,
+    fraud_hooks: Arc<FraudHooks>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    changes: Option<UnboundedSender<AccountChange>>,
     pub workers: Arc<Mutex<Vec<JoinHandle<()>>>>,
 }
 
 impl Publisher {
     pub fn new(mem_store: MemStore, rt: Arc<SpannedRuntime>, worker_count: u16) -> Self {
-        Self{client_sender_map: HashMap::new(), mem_store, rt, worker_count, workers: Arc::new(Mutex::new(Vec::new()))}
+        Self{client_sender_map: HashMap::new(), client_workers: HashMap::new(), client_workers_limit: MIN_CLIENT_WORKERS, sharding: Box::new(Modulo), mem_store, rt, worker_count, channel_capacity: 10, backpressure: Backpressure::default(), priority: None, scaling: None, posted_since_scaling: 0, serial: false, next_sequence: 0, client_sequences: HashMap::new(), watermark: None, counters: Arc::new(ProgressCounters::default()), started: None, dispute_policy: DisputePolicy::default(), rules: Arc::new(RuleSet::default()), fraud_hooks: Arc::new(FraudHooks::default()), middlewares: Vec::new(), changes: None, workers: Arc::new(Mutex::new(Vec::new()))}
+    }
+
+    // with_dispute_policy sets the dispute policy used by engine workers spawned afterwards.
+    pub fn with_dispute_policy(mut self, dispute_policy: DisputePolicy) -> Self {
+        self.dispute_policy = dispute_policy;
+        self
+    }
+
+    // with_rules sets the rules shared by all engine workers spawned afterwards.
+    pub fn with_rules(mut self, rules: Arc<RuleSet>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    // with_fraud_hooks sets the fraud hooks shared by all engine workers spawned afterwards.
+    pub fn with_fraud_hooks(mut self, fraud_hooks: Arc<FraudHooks>) -> Self {
+        self.fraud_hooks = fraud_hooks;
+        self
+    }
+
+    // with_middleware registers a middleware on engine workers spawned afterwards.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    // with_change_feed streams account changes of engine workers spawned
+    // afterwards to changes, in processing order for any single client.
+    pub fn with_change_feed(mut self, changes: UnboundedSender<AccountChange>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    // with_sharding sets the strategy used to route clients to engine workers.
+    pub fn with_sharding(mut self, sharding: Box<dyn ShardingStrategy>) -> Self {
+        self.sharding = sharding;
+        self
+    }
+
+    // with_scaling lets the number of workers grow and shrink between the policy
+    // bounds, starting from the worker count clamped to them.
+    pub fn with_scaling(mut self, scaling: ScalingPolicy) -> Self {
+        if !self.serial {
+            self.worker_count = self.worker_count.clamp(scaling.min_workers, scaling.max_workers);
+        }
+        self.scaling = Some(scaling);
+        self
+    }
+
+    // with_serial enables the strict serial mode, which preserves the global
+    // input order at the cost of parallelism. Scaling is disabled in this mode.
+    pub fn with_serial(mut self, serial: bool) -> Self {
+        self.serial = serial;
+        if serial {
+            self.worker_count = 1;
+        }
+        self
+    }
+
+    // with_channel_capacity sets the queue size of engine workers spawned afterwards.
+    pub fn with_channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = channel_capacity.max(1);
+        self
+    }
+
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    // with_priority_lanes lets high priority transactions of engine workers
+    // spawned afterwards jump ahead of their queued bulk transactions.
+    // A dispute can then be applied before a withdrawal posted earlier for the same client,
+    // so the withdrawal may be rejected for insufficient funds where it would have been applied in input order.
+    // It has no effect on a serial publisher.
+    pub fn with_priority_lanes(mut self, priority: PriorityLanes) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn worker_count(&self) -> u16 {
+        self.worker_count
+    }
+
+    // queue_depths returns the number of queued transactions of each active worker.
+    pub fn queue_depths(&self) -> Vec<u64> {
+        (0..self.worker_count)
+            .map(|shard| self.client_sender_map.get(&shard).map_or(0, |w| w.queued()))
+            .collect()
     }
 
-    // Post transaction will send the given transaction on engine processing
-    // channel based on client ID sharded with worked count.
+    // progress returns the number of posted and processed transactions so far,
+    // the throughput since the first post and the depth of each worker queue.
+    pub fn progress(&self) -> Progress {
+        let elapsed = self.started.map(|started| started.elapsed()).unwrap_or_default();
+        Progress::new(self.next_sequence, &self.counters, elapsed, self.queue_depths())
+    }
+
+    // queue_metrics returns the queue metrics of each active worker.
+    pub fn queue_metrics(&self) -> Vec<QueueMetrics> {
+        (0..self.worker_count)
+            .map(|shard| match self.client_sender_map.get(&shard) {
+                Some(w) => QueueMetrics {
+                    worker: shard,
+                    capacity: self.channel_capacity,
+                    depth: w.queued(),
+                    spilled: w.spilled(),
+                    max_depth: w.max_depth,
+                    full_count: w.full_count,
+                },
+                None => QueueMetrics { worker: shard, capacity: self.channel_capacity, ..QueueMetrics::default() },
+            })
+            .collect()
+    }
+
+    // Post transaction will stamp the given transaction with the next input sequence
+    // and send it on engine processing channel of the worker picked by the sharding strategy.
     // Transactions for different clients will be processed parallelly,
     // and transaction for single client will be processed sequentially.
     pub async fn post_txn(&mut self, transaction: Transaction) -> Result<(), Error> {
-        
-        match self.client_sender_map.get(&(&transaction.client_id % self.worker_count)) {
-            Some(tx) => {
-                tx.send(transaction).await?
+        if let Some(staged) = self.stage_txn(transaction).await? {
+            if let Err(e) = staged.send().await {
+                self.unstage(&staged);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    // stage_txn posts the transaction like post_txn, but returns it staged instead
+    // of waiting when the blocking policy has to wait for the queue of its worker.
+    // Staged transactions must be sent before the next one is posted, to keep their order.
+    pub(crate) async fn stage_txn(&mut self, mut transaction: Transaction) -> Result<Option<Staged>, Error> {
+        self.started.get_or_insert_with(Instant::now);
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        transaction.sequence = Some(sequence);
+        self.client_sequences.entry(transaction.client_id).or_insert(sequence);
+        if let Some(timestamp) = transaction.timestamp {
+            self.watermark = Some(self.watermark.map_or(timestamp, |watermark| watermark.max(timestamp)));
+        }
+
+        for worker in self.client_sender_map.values_mut() {
+            worker.drain_spill()?;
+        }
+        self.scale();
+        let shard = self.route(transaction.client_id);
+        if !self.client_sender_map.contains_key(&shard) {
+            self.spawn_worker(shard).await;
+        }
+
+        let worker = self.client_sender_map.get_mut(&shard).expect("worker spawned above");
+        let client_id = transaction.client_id;
+        // Serial runs keep input order, so nothing goes on the high lane.
+        let lane = if self.serial { Lane::Bulk } else { worker.lane(&transaction, &self.priority) };
+        let staged = match worker.post(shard, transaction, lane, &self.backpressure).await? {
+            Posted::Queued => None,
+            Posted::Blocked(sender, transaction) => Some(Staged { shard, sender, transaction }),
+            Posted::Rejected(transaction) => {
+                self.reject_queue_full(shard, &transaction).await;
+                return Err(Error::new(ErrorKind::QueueFull(shard)));
+            },
+        };
+        self.client_workers.insert(client_id, (shard, worker.sent));
+        if self.client_workers.len() > self.client_workers_limit {
+            self.evict_idle_clients();
+        }
+        Ok(staged)
+    }
+
+    // unstage forgets a staged transaction which could not be sent.
+    pub(crate) fn unstage(&mut self, staged: &Staged) {
+        if let Some(worker) = self.client_sender_map.get_mut(&staged.shard) {
+            worker.sent -= 1;
+            match &worker.bulk_ids {
+                Some(bulk_ids) if staged.sender.same_channel(&worker.sender) => bulk_ids.remove(staged.transaction.id),
+                _ => {},
+            }
+        }
+    }
+
+    // reject_queue_full records the rejection of a transaction refused by a
+    // full queue like the engine records its rejections, so it is reported.
+    async fn reject_queue_full(&self, shard: u16, transaction: &Transaction) {
+        self.counters.record(&OutcomeStatus::Rejected);
+        let outcome = Outcome::new(transaction, OutcomeStatus::Rejected, Some(format!("Queue full for engine worker {}", shard)));
+        if let Err(e) = self.mem_store.add_outcome(outcome).await {
+            tracing::error!("Failed to record outcome for transaction {}: {}", transaction.id, e);
+        }
+        let activity = Activity::new(transaction.client_id, ActivityKind::Rejected, transaction.amount.unwrap_or_default());
+        if let Err(e) = self.mem_store.record_activity(activity).await {
+            tracing::error!("Failed to record activity for transaction {}: {}", transaction.id, e);
+        }
+    }
+
+    // evict_idle_clients drops the clients without queued transactions from the
+    // client workers map, they are routed by the sharding strategy again anyway.
+    fn evict_idle_clients(&mut self) {
+        let workers = &self.client_sender_map;
+        self.client_workers.retain(|_, (shard, sent)| workers.get(shard).is_some_and(|w| w.processed() < *sent));
+        self.client_workers_limit = MIN_CLIENT_WORKERS.max(2 * self.client_workers.len());
+        tracing::debug!("Evicted idle clients, {} busy clients left", self.client_workers.len());
+    }
+
+    // route keeps a client on its worker while it has queued transactions there,
+    // which preserves its order. Idle clients are routed by the sharding strategy.
+    fn route(&mut self, client_id: ClientId) -> u16 {
+        if let Some((shard, sent)) = self.client_workers.get(&client_id) {
+            if self.client_sender_map.get(shard).is_some_and(|w| w.processed() < *sent) {
+                return *shard;
+            }
+        }
+        let loads = self.queue_depths();
+        let shard = self.sharding.route(client_id, &loads);
+        if let Some((previous, _)) = self.client_workers.get(&client_id) {
+            if *previous != shard {
+                tracing::debug!("Sharding {} moved client id {} from worker {} to {}", self.sharding.name(), client_id, previous, shard);
+            }
+        }
+        shard
+    }
+
+    // scale applies the scaling policy. Scaling down retires the last worker
+    // once drained, its clients are routed again on their next transaction.
+    fn scale(&mut self) {
+        let decision = match &self.scaling {
+            Some(scaling) if !self.serial => {
+                self.posted_since_scaling += 1;
+                scaling.decide(&self.queue_depths(), self.posted_since_scaling)
+            },
+            _ => return,
+        };
+        match decision {
+            Scaling::Up => {
+                self.worker_count += 1;
+                tracing::info!("Scaling payment engine workers up to {}", self.worker_count);
             },
-            None => {
-                // Spawn new worker.
-                tracing::info!("Spawning new payment engine worker");
-                let (tx, rx) = tokio::sync::mpsc::channel(10);
-                let worker = Engine::new(self.mem_store.clone()).start(self.rt.clone(), rx).await;
-                tx.send(transaction.clone()).await?;
-                self.workers.lock().await.push(worker);
-                self.client_sender_map.insert(transaction.client_id % self.worker_count, tx);
-                
+            Scaling::Down => {
+                self.worker_count -= 1;
+                let shard = self.worker_count;
+                // Dropping the sender stops the drained worker.
+                self.client_sender_map.remove(&shard);
+                self.client_workers.retain(|_, (worker, _)| *worker != shard);
+                tracing::info!("Scaling payment engine workers down to {}", self.worker_count);
             },
+            Scaling::Keep => return,
         }
-        Ok(())
+        self.posted_since_scaling = 0;
+    }
+
+    async fn spawn_worker(&mut self, shard: u16) {
+        tracing::info!("Spawning new payment engine worker");
+        let (tx, rx) = tokio::sync::mpsc::channel::<Transaction>(self.channel_capacity);
+        let (high, high_rx) = tokio::sync::mpsc::channel::<Transaction>(self.channel_capacity);
+        let bulk_ids = self.priority.as_ref().map(|_| Arc::new(BulkIds::default()));
+        let lanes = Lanes { high: high_rx, bulk: rx, bulk_ids: bulk_ids.clone() };
+        let engine = self.engine();
+        let processed = Arc::new(AtomicU64::new(0));
+        let worker = self.rt.spawn(supervise(self.rt.clone(), engine, self.mem_store.clone(), lanes, processed.clone(), self.counters.clone()));
+        self.workers.lock().await.push(worker);
+        self.client_sender_map.insert(shard, WorkerChannel::new(tx, high, bulk_ids, processed));
+    }
+
+    // engine builds an engine with the configuration of the publisher.
+    fn engine(&self) -> Engine<MemStore> {
+        let mut engine = Engine::new(self.mem_store.clone())
+            .with_dispute_policy(self.dispute_policy.clone())
+            .with_rules(self.rules.clone())
+            .with_fraud_hooks(self.fraud_hooks.clone());
+        for middleware in self.middlewares.iter() {
+            engine = engine.with_middleware(middleware.clone());
+        }
+        if let Some(changes) = &self.changes {
+            engine = engine.with_change_feed(changes.clone());
+        }
+        engine
+    }
+
+    // release_held posts an admin operation completing a transaction held for review.
+    // It goes through the client worker so it is ordered with the client transactions.
+    pub async fn release_held(&mut self, client_id: ClientId, id: TransactionId) -> Result<(), Error> {
+        self.post_txn(Transaction::new(TransactionKind::Release, client_id.0, id.0, None)).await
+    }
+
+    // cancel_held posts an admin operation reverting a transaction held for review.
+    pub async fn cancel_held(&mut self, client_id: ClientId, id: TransactionId) -> Result<(), Error> {
+        self.post_txn(Transaction::new(TransactionKind::Cancel, client_id.0, id.0, None)).await
+    }
+
+    pub async fn get_held_transactions(&self) -> Result<Vec<Transaction>, Error> {
+        self.mem_store.get_held_transactions().await
+    }
+
+    // get_dead_letters returns the transactions which crashed an engine worker.
+    pub async fn get_dead_letters(&self) -> Result<Vec<Transaction>, Error> {
+        self.mem_store.get_dead_letters().await
     }
 
     // shutdown_gracefully will wait until all workers finish processing.
     pub async fn shutdown_gracefully(&mut self) -> Vec<Result<(), Error>> {
-        self.client_sender_map.clear();
         let mut results = Vec::new();
-        for worker in self.workers.lock().await
-            .iter_mut()
-        {
+        for worker in self.client_sender_map.values_mut() {
+            if let Err(e) = worker.flush_spill().await {
+                tracing::error!("Failed to flush spilled transactions: {}", e);
+                results.push(Err(e));
+            }
+        }
+        self.client_sender_map.clear();
+        self.client_workers.clear();
+        // Workers are taken out, so shutting down again does not join them twice.
+        let workers = std::mem::take(&mut *self.workers.lock().await);
+        for worker in workers {
             results.push(worker.await.map_err(|e|
                 Error::new(ErrorKind::JoinError(e))
             ));
         }
+        // Disputes of clients without later transactions are still open,
+        // expire them at the latest timestamp of the run.
+        if let (Some(_), Some(watermark)) = (self.dispute_policy.deadline, self.watermark) {
+            if let Err(e) = self.engine().expire_all_disputes(watermark).await {
+                tracing::error!("Failed to expire open disputes: {}", e);
+                results.push(Err(e));
+            }
+        }
         tracing::info!("Stopped all payment engine workers");
-        return results;
+        results
     }
 
+    // get_report returns the accounts ordered by the input sequence of their first transaction.
     pub async fn get_report(&mut self) -> Result<Pin<Box<dyn futures::Stream<Item = Account> + Send>>, Error> {
+        self.get_report_with(&ReportQuery::default()).await
+    }
+
+    // get_report_with returns the accounts selected and ordered by the query.
+    pub async fn get_report_with(&mut self, query: &ReportQuery) -> Result<Pin<Box<dyn futures::Stream<Item = Account> + Send>>, Error> {
+        let engine = Engine::new(self.mem_store.clone());
+        let accounts = engine.report().await?.collect::<Vec<_>>().await;
+        let accounts = query.apply(accounts, |client| self.client_sequences.get(client).copied());
+        Ok(Box::pin(futures::stream::iter(accounts)))
+    }
+
+    // get_extended_report_with returns the accounts selected and ordered by the
+    // query along with the activity statistics of their client.
+    pub async fn get_extended_report_with(&mut self, query: &ReportQuery) -> Result<Pin<Box<dyn futures::Stream<Item = ExtendedAccount> + Send>>, Error> {
         let engine = Engine::new(self.mem_store.clone());
-        engine.report().await
+        let mut stats = engine.stats().await?.map(|s| (s.client, s)).collect::<HashMap<_, _>>().await;
+        let accounts = self.get_report_with(query).await?.collect::<Vec<_>>().await;
+        let extended = accounts.into_iter()
+            .map(|account| {
+                let stats = stats.remove(&account.client).unwrap_or_else(|| AccountStats::new(account.client));
+                ExtendedAccount { account, stats }
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::pin(futures::stream::iter(extended)))
+    }
+
+    // get_outcomes returns the outcomes ordered by input sequence, whichever worker produced them.
+    pub async fn get_outcomes(&mut self) -> Result<Pin<Box<dyn futures::Stream<Item = Outcome> + Send>>, Error> {
+        let engine = Engine::new(self.mem_store.clone());
+        let mut outcomes = engine.outcomes().await?.collect::<Vec<_>>().await;
+        // Stable sort, so expiries stay ahead of the transaction which triggered them.
+        outcomes.sort_by_key(|o| o.sequence.unwrap_or(u64::MAX));
+        Ok(Box::pin(futures::stream::iter(outcomes)))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use engine::{dispute::{DisputePolicy, ExpiryAction}, engine::Applied, middleware::Middleware};
+    use futures::StreamExt;
+    use mem_store::mem_store::MemStore;
+    use models::{account::Account, error::{Error, ErrorKind}, logger::create_span, ids::TransactionId, outcome::OutcomeStatus, store::Store, transactions::{Transaction, TransactionKind}};
+
+    use crate::{backpressure::Backpressure, priority::PriorityLanes, scaling::ScalingPolicy, sharding::LoadAware};
+    use super::{Publisher, MIN_CLIENT_WORKERS};
+
+    // Gate holds engine workers in pre_apply until it is opened, and reports
+    // the transactions entering it so tests can wait for a worker to pick one.
+    struct Gate {
+        open: tokio::sync::watch::Receiver<bool>,
+        entered: tokio::sync::mpsc::UnboundedSender<TransactionId>,
+    }
+
+    impl Gate {
+        fn new() -> (tokio::sync::watch::Sender<bool>, tokio::sync::mpsc::UnboundedReceiver<TransactionId>, Arc<Self>) {
+            let (open, gate) = tokio::sync::watch::channel(false);
+            let (entered, entries) = tokio::sync::mpsc::unbounded_channel();
+            (open, entries, Arc::new(Self { open: gate, entered }))
+        }
+    }
+
+    #[async_trait]
+    impl Middleware for Gate {
+        fn name(&self) -> &'static str {
+            "gate"
+        }
+
+        async fn pre_apply(&self, _account: &Account, transaction: &Transaction) -> Result<(), Error> {
+            let _ = self.entered.send(transaction.id);
+            let mut open = self.open.clone();
+            while !*open.borrow() {
+                open.changed().await.unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_load_aware_sharding_keeps_client_order() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 3)
+                .with_sharding(Box::new(LoadAware::new(0)));
+            for round in 0..5u64 {
+                for client in 1..=20u32 {
+                    let id = (round * 100 + u64::from(client)) * 2;
+                    publisher.post_txn(Transaction::new(TransactionKind::Deposit, client, id, Some(10.0))).await.unwrap();
+                    publisher.post_txn(Transaction::new(TransactionKind::Withdrawal, client, id + 1, Some(10.0))).await.unwrap();
+                }
+            }
+            publisher.shutdown_gracefully().await;
+
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts.len(), 20);
+            assert!(accounts.iter().all(|a| a.available == 0.0 && a.total == 0.0));
+        })
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_worker_scaling() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let scaling = ScalingPolicy { scale_up_depth: 1, cooldown: 0, ..ScalingPolicy::new(1, 3) };
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 2).with_scaling(scaling);
+            assert_eq!(publisher.worker_count(), 2);
+
+            let mut max_workers = 0;
+            for id in 0..300u64 {
+                let client = (id % 30) as u32;
+                publisher.post_txn(Transaction::new(TransactionKind::Deposit, client, id, Some(1.0))).await.unwrap();
+                max_workers = max_workers.max(publisher.worker_count());
+            }
+            assert!(max_workers <= 3);
+
+            // Drained workers are retired one at a time down to the minimum.
+            for id in 300..302u64 {
+                while publisher.queue_depths().iter().sum::<u64>() > 0 {
+                    tokio::task::yield_now().await;
+                }
+                publisher.post_txn(Transaction::new(TransactionKind::Withdrawal, 0, id, Some(1.0))).await.unwrap();
+            }
+            assert_eq!(publisher.worker_count(), 1);
+            publisher.shutdown_gracefully().await;
+
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts.len(), 30);
+            let total: f32 = accounts.iter().map(|a| a.total).sum();
+            assert_eq!(total, 298.0);
+        })
+    }
+
+    #[test]
+    fn test_idle_clients_evicted() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 2);
+            for client in 0..3000u32 {
+                publisher.post_txn(Transaction::new(TransactionKind::Deposit, client, u64::from(client), Some(1.0))).await.unwrap();
+                // Blocking on full queues keeps the busy clients to a few per worker.
+                assert!(publisher.client_workers.len() <= MIN_CLIENT_WORKERS);
+            }
+            publisher.shutdown_gracefully().await;
+
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts.len(), 3000);
+            assert!(accounts.iter().all(|a| a.available == 1.0));
+        })
+    }
+
+    #[test]
+    fn test_shutdown_expires_open_disputes() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let policy = DisputePolicy::new(None, Some(60), ExpiryAction::Resolve);
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 2).with_dispute_policy(policy);
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0)).with_timestamp(1000)).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::Dispute, 1, 1, None).with_timestamp(1010)).await.unwrap();
+            // Only another client moves the clock past the deadline of the dispute.
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 2, 2, Some(5.0)).with_timestamp(1100)).await.unwrap();
+            publisher.shutdown_gracefully().await;
+
+            let outcomes = publisher.get_outcomes().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(outcomes.len(), 4);
+            assert_eq!(outcomes[3].tx, TransactionId(1));
+            assert_eq!(outcomes[3].status, OutcomeStatus::AutoResolved);
+            assert_eq!(outcomes[3].timestamp, Some(1100));
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts[0].available, 10.0);
+            assert_eq!(accounts[0].held, 0.0);
+        })
+    }
+
+    // Panic panics engine workers after a dispute has been applied.
+    struct Panic;
+
+    #[async_trait]
+    impl Middleware for Panic {
+        fn name(&self) -> &'static str {
+            "panic"
+        }
+
+        async fn post_apply(&self, _account: &Account, transaction: &Transaction, _applied: &Applied) {
+            if transaction.kind == TransactionKind::Dispute {
+                panic!("dispute of tx {}", transaction.id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_worker_restarts_after_panic() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let store = MemStore::default();
+            let mut publisher = Publisher::new(store.clone(), rtc, 1).with_middleware(Arc::new(Panic));
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 2, Some(5.0))).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::Dispute, 1, 1, None)).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 3, Some(1.0))).await.unwrap();
+            publisher.shutdown_gracefully().await;
+
+            let dead_letters = publisher.get_dead_letters().await.unwrap();
+            assert_eq!(dead_letters.len(), 1);
+            assert_eq!(dead_letters[0].id, TransactionId(1));
+            assert_eq!(dead_letters[0].sequence, Some(2));
+            // The dispute was applied before the panic, it is rolled back.
+            assert!(!store.get_transaction(TransactionId(1)).await.unwrap().under_dispute);
+
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts[0].available, 16.0);
+            assert_eq!(accounts[0].held, 0.0);
+            assert_eq!(accounts[0].total, 16.0);
+            let statuses = publisher.get_outcomes().await.unwrap().map(|o| o.status).collect::<Vec<_>>().await;
+            assert_eq!(statuses, vec![OutcomeStatus::Applied, OutcomeStatus::Applied, OutcomeStatus::Failed, OutcomeStatus::Applied]);
+        })
+    }
+
+    #[test]
+    fn test_fail_fast_backpressure() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (open, mut entered, gate) = Gate::new();
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 1)
+                .with_channel_capacity(1)
+                .with_backpressure(Backpressure::FailFast)
+                .with_middleware(gate);
+
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 1, Some(1.0))).await.unwrap();
+            // Wait for the worker to pick the first transaction, the second fills the queue.
+            assert_eq!(entered.recv().await, Some(TransactionId(1)));
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 2, Some(1.0))).await.unwrap();
+            let err = publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 3, Some(1.0))).await.unwrap_err();
+            assert!(matches!(*err.kind, ErrorKind::QueueFull(0)));
+
+            let metrics = publisher.queue_metrics();
+            assert_eq!(metrics[0].depth, 2);
+            assert_eq!(metrics[0].full_count, 1);
+
+            open.send(true).unwrap();
+            publisher.shutdown_gracefully().await;
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts[0].total, 2.0);
+            // The rejected transaction is reported and counted as processed.
+            let outcomes = publisher.get_outcomes().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(outcomes[2].tx, TransactionId(3));
+            assert_eq!(outcomes[2].status, OutcomeStatus::Rejected);
+            assert_eq!(outcomes[2].reason.as_deref(), Some("Queue full for engine worker 0"));
+            let progress = publisher.progress();
+            assert_eq!(progress.posted, 3);
+            assert_eq!(progress.rejected, 1);
+        })
+    }
+
+    #[test]
+    fn test_spill_to_disk_backpressure() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (open, _entered, gate) = Gate::new();
+            let dir = std::env::temp_dir().join(format!("publisher-spill-test-{}", std::process::id()));
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 1)
+                .with_channel_capacity(2)
+                .with_backpressure(Backpressure::SpillToDisk(dir.clone()))
+                .with_middleware(gate);
+
+            for id in 0..10 {
+                publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, id, Some(1.0))).await.unwrap();
+            }
+            publisher.post_txn(Transaction::new(TransactionKind::Withdrawal, 1, 10, Some(10.0))).await.unwrap();
+
+            let metrics = publisher.queue_metrics();
+            assert_eq!(metrics[0].depth, 11);
+            assert!(metrics[0].spilled >= 8);
+
+            open.send(true).unwrap();
+            publisher.shutdown_gracefully().await;
+            // The withdrawal is only applied after all spilled deposits.
+            let accounts = publisher.get_report().await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(accounts[0].total, 0.0);
+            let _ = std::fs::remove_dir_all(&dir);
+        })
+    }
+
+    #[test]
+    fn test_outputs_ordered_by_sequence() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            for serial in [false, true] {
+                let store = MemStore::default();
+                let mut publisher = Publisher::new(store.clone(), rtc.clone(), 4).with_serial(serial);
+                for id in 0..40u64 {
+                    let client = (40 - id as u32) % 7;
+                    publisher.post_txn(Transaction::new(TransactionKind::Deposit, client, id, Some(1.0))).await.unwrap();
+                }
+                publisher.shutdown_gracefully().await;
+
+                let sequences = publisher.get_outcomes().await.unwrap().map(|o| o.sequence.unwrap()).collect::<Vec<_>>().await;
+                assert_eq!(sequences, (0..40).collect::<Vec<_>>());
+                let clients = publisher.get_report().await.unwrap().map(|a| a.client.0).collect::<Vec<_>>().await;
+                assert_eq!(clients, vec![5, 4, 3, 2, 1, 0, 6]);
+
+                // In serial mode the engine itself processes transactions in input order.
+                if serial {
+                    let stored = store.get_all_outcomes().await.unwrap().map(|o| o.tx.0).collect::<Vec<_>>().await;
+                    assert_eq!(stored, (0..40).collect::<Vec<_>>());
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_priority_lanes() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (open, mut entered, gate) = Gate::new();
+            let store = MemStore::default();
+            let mut publisher = Publisher::new(store.clone(), rtc, 1)
+                .with_priority_lanes(PriorityLanes::default())
+                .with_middleware(gate);
+
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 1, Some(10.0))).await.unwrap();
+            // Wait for the worker to pick the first deposit.
+            assert_eq!(entered.recv().await, Some(TransactionId(1)));
+            for id in 2..5 {
+                publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, id, Some(10.0))).await.unwrap();
+            }
+            // Deposit 3 is still queued, so its dispute must wait behind it.
+            publisher.post_txn(Transaction::new(TransactionKind::Dispute, 1, 3, None)).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::Dispute, 1, 1, None)).await.unwrap();
+            publisher.post_txn(Transaction::new(TransactionKind::ChargeBack, 1, 1, None)).await.unwrap();
+
+            open.send(true).unwrap();
+            publisher.shutdown_gracefully().await;
+
+            let processed = store.get_all_outcomes().await.unwrap().map(|o| (o.kind, o.tx.0)).collect::<Vec<_>>().await;
+            assert_eq!(processed, vec![
+                (TransactionKind::Deposit, 1),
+                (TransactionKind::Dispute, 1),
+                (TransactionKind::ChargeBack, 1),
+                (TransactionKind::Deposit, 2),
+                (TransactionKind::Deposit, 3),
+                (TransactionKind::Deposit, 4),
+                (TransactionKind::Dispute, 3),
+            ]);
+        })
+    }
+
+    #[test]
+    fn test_priority_lanes_fail_fast() {
+        let rt = Arc::new(models::infra::get_runtime(1, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (open, mut entered, gate) = Gate::new();
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 1)
+                .with_channel_capacity(1)
+                .with_backpressure(Backpressure::FailFast)
+                .with_priority_lanes(PriorityLanes::default())
+                .with_middleware(gate);
+
+            publisher.post_txn(Transaction::new(TransactionKind::Deposit, 1, 1, Some(1.0))).await.unwrap();
+            assert_eq!(entered.recv().await, Some(TransactionId(1)));
+            // The dispute fills the high lane, the fail fast policy rejects the chargeback.
+            publisher.post_txn(Transaction::new(TransactionKind::Dispute, 1, 1, None)).await.unwrap();
+            let err = publisher.post_txn(Transaction::new(TransactionKind::ChargeBack, 1, 1, None)).await.unwrap_err();
+            assert!(matches!(*err.kind, ErrorKind::QueueFull(0)));
+            assert_eq!(publisher.queue_metrics()[0].full_count, 1);
+
+            open.send(true).unwrap();
+            publisher.shutdown_gracefully().await;
+            let statuses = publisher.get_outcomes().await.unwrap().map(|o| o.status).collect::<Vec<_>>().await;
+            assert_eq!(statuses, vec![OutcomeStatus::Applied, OutcomeStatus::Applied, OutcomeStatus::Rejected]);
+        })
+    }
+
+    #[test]
+    fn test_change_feed() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (changes, mut feed) = tokio::sync::mpsc::unbounded_channel();
+            let mut publisher = Publisher::new(MemStore::default(), rtc, 3).with_change_feed(changes);
+            for id in 0..30u64 {
+                publisher.post_txn(Transaction::new(TransactionKind::Deposit, (id % 3) as u32, id, Some(1.0))).await.unwrap();
+            }
+            publisher.shutdown_gracefully().await;
+
+            let mut totals = std::collections::HashMap::new();
+            while let Ok(change) = feed.try_recv() {
+                // Changes of a client arrive in its processing order.
+                let total = totals.entry(change.account.client.0).or_insert(0.0);
+                assert_eq!(change.account.total, *total + 1.0);
+                assert_eq!(change.sequence, Some(change.tx.0));
+                *total = change.account.total;
+            }
+            assert_eq!(totals.values().copied().collect::<Vec<_>>(), vec![10.0; 3]);
+        })
+    }
+}
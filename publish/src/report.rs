@@ -0,0 +1,98 @@
+use std::{cmp::Ordering, ops::RangeInclusive};
+
+use models::{account::Account, ids::{ClientId, RawClientId}};
+
+// ReportOrder is the order of the accounts in the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportOrder {
+    // By the input sequence of the first transaction of each client.
+    #[default]
+    Input,
+    Client,
+    // Largest total first, ties by client.
+    Total,
+}
+
+impl ReportOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "input" => Some(ReportOrder::Input),
+            "client" => Some(ReportOrder::Client),
+            "total" => Some(ReportOrder::Total),
+            _ => None,
+        }
+    }
+}
+
+// ReportQuery selects and orders the accounts of a report.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportQuery {
+    pub order: ReportOrder,
+    pub locked_only: bool,
+    // Skip accounts without available or held funds.
+    pub non_zero_only: bool,
+    // When set, only clients in one of the inclusive ranges are reported.
+    pub clients: Vec<RangeInclusive<RawClientId>>,
+}
+
+impl ReportQuery {
+    pub fn matches(&self, account: &Account) -> bool {
+        (!self.locked_only || account.locked)
+            && (!self.non_zero_only || account.available != 0.0 || account.held != 0.0)
+            && (self.clients.is_empty() || self.clients.iter().any(|range| range.contains(&account.client.0)))
+    }
+
+    // apply filters and sorts the accounts, first_sequence gives the input
+    // sequence of the first transaction of a client for ReportOrder::Input.
+    pub fn apply(&self, accounts: Vec<Account>, first_sequence: impl Fn(&ClientId) -> Option<u64>) -> Vec<Account> {
+        let mut accounts = accounts.into_iter().filter(|a| self.matches(a)).collect::<Vec<_>>();
+        match self.order {
+            ReportOrder::Input => accounts.sort_by_key(|a| (first_sequence(&a.client).unwrap_or(u64::MAX), a.client)),
+            ReportOrder::Client => accounts.sort_by_key(|a| a.client),
+            ReportOrder::Total => accounts.sort_by(|a, b| match b.total.total_cmp(&a.total) {
+                Ordering::Equal => a.client.cmp(&b.client),
+                ordering => ordering,
+            }),
+        }
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::account::Account;
+
+    use super::{ReportOrder, ReportQuery};
+
+    fn clients(query: &ReportQuery) -> Vec<u32> {
+        let accounts = vec![
+            Account::load(3, 5.0, 0.0, false),
+            Account::load(1, 0.0, 0.0, true),
+            Account::load(7, 2.0, 3.0, true),
+            Account::load(5, 5.0, 0.0, false),
+        ];
+        // Clients in reverse id order of their first transaction.
+        query.apply(accounts, |client| Some(10 - client.0 as u64)).into_iter().map(|a| a.client.0).collect()
+    }
+
+    #[test]
+    fn test_report_order() {
+        assert_eq!(clients(&ReportQuery::default()), vec![7, 5, 3, 1]);
+        assert_eq!(clients(&ReportQuery { order: ReportOrder::Client, ..ReportQuery::default() }), vec![1, 3, 5, 7]);
+        assert_eq!(clients(&ReportQuery { order: ReportOrder::Total, ..ReportQuery::default() }), vec![3, 5, 7, 1]);
+        assert_eq!(ReportOrder::from_name("total"), Some(ReportOrder::Total));
+        assert_eq!(ReportOrder::from_name("balance"), None);
+    }
+
+    #[test]
+    fn test_report_filters() {
+        let query = ReportQuery { order: ReportOrder::Client, locked_only: true, ..ReportQuery::default() };
+        assert_eq!(clients(&query), vec![1, 7]);
+        let query = ReportQuery { order: ReportOrder::Client, non_zero_only: true, ..ReportQuery::default() };
+        assert_eq!(clients(&query), vec![3, 5, 7]);
+        let query = ReportQuery { order: ReportOrder::Client, clients: vec![1..=3, 7..=9], ..ReportQuery::default() };
+        assert_eq!(clients(&query), vec![1, 3, 7]);
+        let query = ReportQuery { locked_only: true, non_zero_only: true, clients: vec![5..=10], ..ReportQuery::default() };
+        assert_eq!(clients(&query), vec![7]);
+    }
+}
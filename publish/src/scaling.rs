@@ -0,0 +1,86 @@
+// Scaling is the decision of a scaling policy after a transaction is posted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    Up,
+    Down,
+    Keep,
+}
+
+// ScalingPolicy grows the worker pool between min and max workers based on
+// the depth of the worker queues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalingPolicy {
+    pub min_workers: u16,
+    pub max_workers: u16,
+    // Scale up once the average queue depth reaches this many transactions.
+    pub scale_up_depth: u64,
+    // Scale down once the remaining workers would average at most this many
+    // queued transactions and the last worker is drained.
+    pub scale_down_depth: u64,
+    // Minimum number of posted transactions between two scaling decisions.
+    pub cooldown: u64,
+}
+
+impl ScalingPolicy {
+    pub fn new(min_workers: u16, max_workers: u16) -> Self {
+        let min_workers = min_workers.max(1);
+        Self {
+            min_workers,
+            max_workers: max_workers.max(min_workers),
+            scale_up_depth: 8,
+            scale_down_depth: 1,
+            cooldown: 64,
+        }
+    }
+
+    // decide takes the queue depth of each active worker and the number of
+    // transactions posted since the last scaling.
+    pub fn decide(&self, loads: &[u64], posted_since_scaling: u64) -> Scaling {
+        let workers = loads.len() as u64;
+        if workers < u64::from(self.min_workers) {
+            return Scaling::Up;
+        }
+        if posted_since_scaling < self.cooldown {
+            return Scaling::Keep;
+        }
+
+        let queued: u64 = loads.iter().sum();
+        if workers < u64::from(self.max_workers) && queued >= self.scale_up_depth * workers {
+            return Scaling::Up;
+        }
+        if workers > u64::from(self.min_workers)
+            && loads.last() == Some(&0)
+            && queued <= self.scale_down_depth * (workers - 1) {
+            return Scaling::Down;
+        }
+        Scaling::Keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scaling, ScalingPolicy};
+
+    #[test]
+    fn test_scaling_decision() {
+        let policy = ScalingPolicy { cooldown: 10, ..ScalingPolicy::new(1, 3) };
+
+        assert_eq!(policy.decide(&[], 0), Scaling::Up);
+        assert_eq!(policy.decide(&[9], 5), Scaling::Keep);
+        assert_eq!(policy.decide(&[9], 10), Scaling::Up);
+        assert_eq!(policy.decide(&[9, 7], 10), Scaling::Up);
+        assert_eq!(policy.decide(&[10, 10, 10], 10), Scaling::Keep);
+
+        assert_eq!(policy.decide(&[3, 1, 0], 10), Scaling::Keep);
+        assert_eq!(policy.decide(&[1, 1, 0], 10), Scaling::Down);
+        assert_eq!(policy.decide(&[0, 0, 1], 10), Scaling::Keep);
+        assert_eq!(policy.decide(&[0], 10), Scaling::Keep);
+    }
+
+    #[test]
+    fn test_scaling_bounds() {
+        let policy = ScalingPolicy::new(0, 0);
+        assert_eq!(policy.min_workers, 1);
+        assert_eq!(policy.max_workers, 1);
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, HashMap};
+
+use models::ids::ClientId;
+
+// ShardingStrategy picks the engine worker of a client.
+// The publisher only consults it for clients without queued transactions, so a
+// strategy is free to move a client to another worker without reordering it.
+pub trait ShardingStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // route returns the worker of the client in 0..loads.len(),
+    // loads holds the number of queued transactions of each worker.
+    fn route(&mut self, client_id: ClientId, loads: &[u64]) -> u16;
+}
+
+// ShardingKind names the built-in strategies, used for configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ShardingKind {
+    #[default]
+    Modulo,
+    ConsistentHash,
+    LoadAware,
+}
+
+impl ShardingKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "modulo" => Some(ShardingKind::Modulo),
+            "consistent-hash" => Some(ShardingKind::ConsistentHash),
+            "load-aware" => Some(ShardingKind::LoadAware),
+            _ => None,
+        }
+    }
+
+    pub fn strategy(&self) -> Box<dyn ShardingStrategy> {
+        match self {
+            ShardingKind::Modulo => Box::new(Modulo),
+            ShardingKind::ConsistentHash => Box::new(ConsistentHash::default()),
+            ShardingKind::LoadAware => Box::new(LoadAware::default()),
+        }
+    }
+}
+
+// Modulo routes by client id % worker count.
+pub struct Modulo;
+
+impl ShardingStrategy for Modulo {
+    fn name(&self) -> &'static str {
+        "modulo"
+    }
+
+    fn route(&mut self, client_id: ClientId, loads: &[u64]) -> u16 {
+        client_id.shard(loads.len() as u16)
+    }
+}
+
+// ConsistentHash places workers on a hash ring with replicas virtual nodes each,
+// a client goes to the first node following its hash. Changing the worker count
+// only moves the clients of the affected ring segments.
+pub struct ConsistentHash {
+    replicas: u32,
+    workers: usize,
+    ring: BTreeMap<u64, u16>,
+}
+
+impl ConsistentHash {
+    pub fn new(replicas: u32) -> Self {
+        Self { replicas: replicas.max(1), workers: 0, ring: BTreeMap::new() }
+    }
+
+    fn build_ring(&mut self, workers: usize) {
+        self.ring.clear();
+        for worker in 0..workers as u16 {
+            for replica in 0..self.replicas {
+                self.ring.insert(hash((u64::from(worker) << 32) | u64::from(replica)), worker);
+            }
+        }
+        self.workers = workers;
+    }
+}
+
+impl Default for ConsistentHash {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl ShardingStrategy for ConsistentHash {
+    fn name(&self) -> &'static str {
+        "consistent_hash"
+    }
+
+    fn route(&mut self, client_id: ClientId, loads: &[u64]) -> u16 {
+        if self.workers != loads.len() {
+            self.build_ring(loads.len());
+        }
+        let key = hash(u64::from(client_id.0) ^ 0x5bd1_e995_0000_0000);
+        self.ring.range(key..).next()
+            .or_else(|| self.ring.iter().next())
+            .map_or(0, |(_, worker)| *worker)
+    }
+}
+
+// LoadAware sends new clients to the least loaded worker and keeps them there
+// until their worker is more than threshold transactions behind the least
+// loaded one, at which point the client is migrated.
+pub struct LoadAware {
+    threshold: u64,
+    assigned: HashMap<ClientId, u16>,
+}
+
+impl LoadAware {
+    pub fn new(threshold: u64) -> Self {
+        Self { threshold, assigned: HashMap::new() }
+    }
+}
+
+impl Default for LoadAware {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl ShardingStrategy for LoadAware {
+    fn name(&self) -> &'static str {
+        "load_aware"
+    }
+
+    fn route(&mut self, client_id: ClientId, loads: &[u64]) -> u16 {
+        let least = (0..loads.len()).min_by_key(|w| loads[*w]).unwrap_or(0) as u16;
+        match self.assigned.get(&client_id) {
+            Some(worker) if (*worker as usize) < loads.len()
+                && loads[*worker as usize] <= loads[least as usize] + self.threshold => *worker,
+            _ => {
+                tracing::debug!("Assigning client id {} to worker {}", client_id, least);
+                self.assigned.insert(client_id, least);
+                least
+            },
+        }
+    }
+}
+
+// splitmix64 finalizer, stable across runs and platforms unlike the std hasher.
+fn hash(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use models::ids::ClientId;
+
+    use super::{ConsistentHash, LoadAware, Modulo, ShardingStrategy};
+
+    #[test]
+    fn test_modulo() {
+        let mut sharding = Modulo;
+        assert_eq!(sharding.route(ClientId(7), &[0, 0, 0]), 1);
+        assert_eq!(sharding.route(ClientId(9), &[0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let mut sharding = ConsistentHash::default();
+        let before = (0..1000).map(|c| sharding.route(ClientId(c), &[0; 4])).collect::<Vec<_>>();
+        for worker in 0..4 {
+            assert!(before.iter().filter(|w| **w == worker).count() > 100);
+        }
+        assert_eq!(before, (0..1000).map(|c| sharding.route(ClientId(c), &[0; 4])).collect::<Vec<_>>());
+
+        // Adding a worker only moves clients to the new worker.
+        let after = (0..1000).map(|c| sharding.route(ClientId(c), &[0; 5])).collect::<Vec<_>>();
+        let moved = before.iter().zip(after.iter()).filter(|(b, a)| b != a).collect::<Vec<_>>();
+        assert!(moved.iter().all(|(_, a)| **a == 4));
+        assert!(moved.len() < 400);
+    }
+
+    #[test]
+    fn test_load_aware() {
+        let mut sharding = LoadAware::new(2);
+        assert_eq!(sharding.route(ClientId(1), &[3, 0, 1]), 1);
+        assert_eq!(sharding.route(ClientId(2), &[3, 1, 0]), 2);
+        // Client 1 stays on its worker while within threshold of the least loaded.
+        assert_eq!(sharding.route(ClientId(1), &[0, 2, 1]), 1);
+        // and migrates once its worker falls behind.
+        assert_eq!(sharding.route(ClientId(1), &[0, 3, 1]), 0);
+        // Clients of removed workers are reassigned.
+        assert_eq!(sharding.route(ClientId(2), &[1, 0]), 1);
+    }
+}
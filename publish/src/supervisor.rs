@@ -0,0 +1,84 @@
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+
+use engine::engine::{Engine, Snapshot};
+use models::{infra::SpannedRuntime, outcome::{Outcome, OutcomeStatus}, store::Store, transactions::Transaction};
+use tokio::sync::Mutex;
+
+use crate::{priority::Lanes, progress::ProgressCounters};
+
+// supervise runs an engine worker on lanes and restarts it whenever it panics.
+// The transaction being processed when the worker panicked is rolled back and
+// sent to the dead letters, and the restarted worker carries on with the next one.
+pub(crate) async fn supervise<S>(rt: Arc<SpannedRuntime>, engine: Engine<S>, store: S, lanes: Lanes, processed: Arc<AtomicU64>, counters: Arc<ProgressCounters>)
+where S: Store + Clone + Send + 'static {
+    let lanes = Arc::new(Mutex::new(lanes));
+    let current = Arc::new(std::sync::Mutex::new(None));
+    let snapshots = Arc::new(std::sync::Mutex::new(None));
+    let engine = engine.with_snapshots(snapshots.clone());
+    loop {
+        let worker = rt.spawn(run(engine.clone(), lanes.clone(), current.clone(), snapshots.clone(), processed.clone(), counters.clone()));
+        let err = match worker.await {
+            Ok(()) => return,
+            Err(e) if e.is_panic() => e,
+            Err(e) => {
+                tracing::error!("Payment engine worker cancelled: {}", e);
+                return;
+            },
+        };
+
+        let reason = panic_message(err.into_panic());
+        let failed = current.lock().expect("supervisor lock poisoned").take();
+        let snapshot = snapshots.lock().expect("supervisor lock poisoned").take();
+        match failed {
+            Some(transaction) => {
+                tracing::error!("Payment engine worker panicked on transaction {}: {}", transaction.id, reason);
+                dead_letter(&engine, &store, transaction, snapshot, reason).await;
+                counters.record(&OutcomeStatus::Failed);
+                processed.fetch_add(1, Ordering::Release);
+            },
+            None => tracing::error!("Payment engine worker panicked: {}", reason),
+        }
+        tracing::info!("Restarting payment engine worker");
+    }
+}
+
+async fn run<S>(engine: Engine<S>, lanes: Arc<Mutex<Lanes>>, current: Arc<std::sync::Mutex<Option<Transaction>>>, snapshots: Arc<std::sync::Mutex<Option<Snapshot>>>, processed: Arc<AtomicU64>, counters: Arc<ProgressCounters>)
+where S: Store + Clone + Send + 'static {
+    loop {
+        let transaction = match lanes.lock().await.recv().await {
+            Some(transaction) => transaction,
+            None => return,
+        };
+        *current.lock().expect("supervisor lock poisoned") = Some(transaction.clone());
+        let outcome = engine.process(&transaction).await.outcome;
+        counters.record(&outcome.status);
+        *current.lock().expect("supervisor lock poisoned") = None;
+        snapshots.lock().expect("supervisor lock poisoned").take();
+        processed.fetch_add(1, Ordering::Release);
+    }
+}
+
+// dead_letter restores the snapshot taken before the failed transaction was
+// applied. Without snapshot the worker panicked before changing anything.
+async fn dead_letter<S>(engine: &Engine<S>, store: &S, transaction: Transaction, snapshot: Option<Snapshot>, reason: String)
+where S: Store + Clone + Send + 'static {
+    if let Some(snapshot) = snapshot {
+        if let Err(e) = engine.restore(&snapshot).await {
+            tracing::error!("Failed to rollback transaction {}: {}", transaction.id, e);
+        }
+    }
+    let outcome = Outcome::new(&transaction, OutcomeStatus::Failed, Some(format!("Engine worker panicked: {}", reason)));
+    if let Err(e) = store.add_outcome(outcome).await {
+        tracing::error!("Failed to record outcome for transaction {}: {}", transaction.id, e);
+    }
+    if let Err(e) = store.add_dead_letter(transaction).await {
+        tracing::error!("Failed to add dead letter transaction: {}", e);
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic".to_string(), |msg| msg.to_string()),
+    }
+}
@@ -0,0 +1,281 @@
+use std::{collections::HashMap, pin::Pin, sync::{atomic::{AtomicU64, Ordering}, Arc, RwLock}};
+
+use mem_store::mem_store::MemStore;
+use models::{account::Account, error::{Error, ErrorKind}, infra::SpannedRuntime, outcome::Outcome, transactions::Transaction};
+use tokio::sync::Mutex;
+
+use crate::{backpressure::Backpressure, progress::Progress, publish::Publisher, report::ReportQuery};
+
+// TenantConfig holds the worker settings and limits of a tenant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TenantConfig {
+    pub worker_count: u16,
+    pub channel_capacity: usize,
+    pub backpressure: Backpressure,
+    // Maximum number of transactions accepted for the tenant, unlimited when None.
+    pub max_transactions: Option<u64>,
+}
+
+impl Default for TenantConfig {
+    fn default() -> Self {
+        Self { worker_count: 2, channel_capacity: 10, backpressure: Backpressure::default(), max_transactions: None }
+    }
+}
+
+// PublisherSetup customizes the publisher of a tenant, e.g. with rules or middlewares.
+pub type PublisherSetup = Arc<dyn Fn(&str, Publisher) -> Publisher + Send + Sync>;
+
+struct Tenant {
+    publisher: Mutex<Publisher>,
+    // Serializes posts of the tenant, so a transaction waiting for a full queue
+    // is sent before the next one without holding the publisher lock.
+    feeder: Mutex<()>,
+    store: MemStore,
+    max_transactions: Option<u64>,
+    accepted: AtomicU64,
+}
+
+// TenantPublisher routes transactions by (tenant, client). Each tenant gets its
+// own store and publisher with its own workers, so a tenant saturating its
+// queues or failing its file neither blocks nor corrupts the others.
+// Methods take &self so tenants can be fed concurrently from separate tasks.
+pub struct TenantPublisher {
+    rt: Arc<SpannedRuntime>,
+    default_config: TenantConfig,
+    configs: HashMap<String, TenantConfig>,
+    setup: Option<PublisherSetup>,
+    tenants: RwLock<HashMap<String, Arc<Tenant>>>,
+}
+
+impl TenantPublisher {
+    pub fn new(rt: Arc<SpannedRuntime>) -> Self {
+        Self { rt, default_config: TenantConfig::default(), configs: HashMap::new(), setup: None, tenants: RwLock::new(HashMap::new()) }
+    }
+
+    // with_default_config sets the config of tenants without a config of their own.
+    pub fn with_default_config(mut self, config: TenantConfig) -> Self {
+        self.default_config = config;
+        self
+    }
+
+    pub fn with_tenant_config(mut self, tenant: &str, config: TenantConfig) -> Self {
+        self.configs.insert(tenant.to_string(), config);
+        self
+    }
+
+    pub fn with_setup(mut self, setup: PublisherSetup) -> Self {
+        self.setup = Some(setup);
+        self
+    }
+
+    // post_txn posts the transaction to the publisher of the tenant, which is
+    // created on its first transaction.
+    pub async fn post_txn(&self, tenant: &str, transaction: Transaction) -> Result<(), Error> {
+        let state = self.tenant(tenant);
+        let accepted = state.accepted.fetch_add(1, Ordering::Relaxed);
+        if state.max_transactions.is_some_and(|max| accepted >= max) {
+            state.accepted.fetch_sub(1, Ordering::Relaxed);
+            tracing::warn!("Tenant {} exceeded its limit, rejecting transaction {}", tenant, transaction.id);
+            return Err(Error::new(ErrorKind::TenantError(tenant.to_string(), "transaction limit exceeded".to_string())));
+        }
+        let _feeder = state.feeder.lock().await;
+        let staged = state.publisher.lock().await.stage_txn(transaction).await;
+        let result = match staged {
+            Ok(Some(staged)) => {
+                let sent = staged.send().await;
+                if sent.is_err() {
+                    state.publisher.lock().await.unstage(&staged);
+                }
+                sent
+            },
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            state.accepted.fetch_sub(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    // tenants returns the known tenants in name order.
+    pub fn tenants(&self) -> Vec<String> {
+        let mut tenants = self.tenants.read().expect("tenants lock poisoned").keys().cloned().collect::<Vec<_>>();
+        tenants.sort();
+        tenants
+    }
+
+    pub fn store(&self, tenant: &str) -> Result<MemStore, Error> {
+        Ok(self.get(tenant)?.store.clone())
+    }
+
+    pub async fn progress(&self, tenant: &str) -> Result<Progress, Error> {
+        Ok(self.get(tenant)?.publisher.lock().await.progress())
+    }
+
+    pub async fn get_report(&self, tenant: &str) -> Result<Pin<Box<dyn futures::Stream<Item = Account> + Send>>, Error> {
+        self.get(tenant)?.publisher.lock().await.get_report().await
+    }
+
+    pub async fn get_report_with(&self, tenant: &str, query: &ReportQuery) -> Result<Pin<Box<dyn futures::Stream<Item = Account> + Send>>, Error> {
+        self.get(tenant)?.publisher.lock().await.get_report_with(query).await
+    }
+
+    pub async fn get_outcomes(&self, tenant: &str) -> Result<Pin<Box<dyn futures::Stream<Item = Outcome> + Send>>, Error> {
+        self.get(tenant)?.publisher.lock().await.get_outcomes().await
+    }
+
+    // shutdown_tenant waits until the workers of the tenant finish processing.
+    pub async fn shutdown_tenant(&self, tenant: &str) -> Result<Vec<Result<(), Error>>, Error> {
+        Ok(self.get(tenant)?.publisher.lock().await.shutdown_gracefully().await)
+    }
+
+    // shutdown_gracefully waits until the workers of all tenants finish processing.
+    // Tenants are shut down concurrently, so a stalled tenant does not hold up the others.
+    pub async fn shutdown_gracefully(&self) -> HashMap<String, Vec<Result<(), Error>>> {
+        let tenants = self.tenants();
+        let results = futures::future::join_all(tenants.iter().map(|tenant| self.shutdown_tenant(tenant))).await;
+        tenants.into_iter().zip(results)
+            .filter_map(|(tenant, result)| result.ok().map(|result| (tenant, result)))
+            .collect()
+    }
+
+    fn get(&self, tenant: &str) -> Result<Arc<Tenant>, Error> {
+        self.tenants.read().expect("tenants lock poisoned").get(tenant).cloned()
+            .ok_or_else(|| Error::new(ErrorKind::TenantError(tenant.to_string(), "unknown tenant".to_string())))
+    }
+
+    fn tenant(&self, tenant: &str) -> Arc<Tenant> {
+        if let Ok(state) = self.get(tenant) {
+            return state;
+        }
+        let mut tenants = self.tenants.write().expect("tenants lock poisoned");
+        tenants.entry(tenant.to_string()).or_insert_with(|| {
+            tracing::info!("Creating publisher for tenant {}", tenant);
+            let config = self.configs.get(tenant).unwrap_or(&self.default_config);
+            let store = MemStore::default();
+            let mut publisher = Publisher::new(store.clone(), self.rt.clone(), config.worker_count)
+                .with_channel_capacity(config.channel_capacity)
+                .with_backpressure(config.backpressure.clone());
+            if let Some(setup) = &self.setup {
+                publisher = setup(tenant, publisher);
+            }
+            Arc::new(Tenant { publisher: Mutex::new(publisher), feeder: Mutex::new(()), store, max_transactions: config.max_transactions, accepted: AtomicU64::new(0) })
+        }).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use engine::middleware::Middleware;
+    use futures::StreamExt;
+    use models::{account::Account, error::{Error, ErrorKind}, logger::create_span, transactions::{Transaction, TransactionKind}};
+
+    use tokio::sync::watch;
+
+    use super::{TenantConfig, TenantPublisher};
+
+    // Stall holds transactions of a tenant until it is opened.
+    struct Stall(watch::Receiver<bool>);
+
+    #[async_trait]
+    impl Middleware for Stall {
+        fn name(&self) -> &'static str {
+            "stall"
+        }
+
+        async fn pre_apply(&self, _account: &Account, _transaction: &Transaction) -> Result<(), Error> {
+            let mut open = self.0.clone();
+            while !*open.borrow() && open.changed().await.is_ok() {}
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tenant_isolation() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (_open, closed) = watch::channel(false);
+            let publisher = Arc::new(TenantPublisher::new(rtc.clone())
+                .with_tenant_config("stalled", TenantConfig { worker_count: 1, channel_capacity: 1, ..TenantConfig::default() })
+                .with_tenant_config("limited", TenantConfig { max_transactions: Some(2), ..TenantConfig::default() })
+                .with_setup(Arc::new(move |tenant, publisher| match tenant {
+                    "stalled" => publisher.with_middleware(Arc::new(Stall(closed.clone()))),
+                    _ => publisher,
+                })));
+
+            // The stalled tenant blocks once its queue is full.
+            let stalled = publisher.clone();
+            rtc.spawn(async move {
+                for id in 0..10 {
+                    stalled.post_txn("stalled", Transaction::new(TransactionKind::Deposit, 1, id, Some(1.0))).await.unwrap();
+                }
+            });
+
+            for id in 0..3 {
+                let result = publisher.post_txn("limited", Transaction::new(TransactionKind::Deposit, 1, id, Some(5.0))).await;
+                if id < 2 {
+                    result.unwrap();
+                } else {
+                    assert!(matches!(&*result.unwrap_err().kind, ErrorKind::TenantError(tenant, _) if tenant == "limited"));
+                }
+            }
+            // Same client and transaction ids in another tenant do not collide.
+            publisher.post_txn("other", Transaction::new(TransactionKind::Deposit, 1, 0, Some(7.0))).await.unwrap();
+
+            publisher.shutdown_tenant("limited").await.unwrap();
+            publisher.shutdown_tenant("other").await.unwrap();
+            let limited = publisher.get_report("limited").await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(limited[0].total, 10.0);
+            let other = publisher.get_report("other").await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(other[0].total, 7.0);
+
+            assert_eq!(publisher.tenants(), vec!["limited", "other", "stalled"]);
+            assert!(publisher.get_report("unknown").await.is_err());
+        })
+    }
+
+    #[test]
+    fn test_shutdown_with_stalled_tenant() {
+        let rt = Arc::new(models::infra::get_runtime(2, 1, create_span()).unwrap());
+        let rtc = rt.clone();
+        rt.block_on(async move {
+            let (open, closed) = watch::channel(false);
+            let publisher = Arc::new(TenantPublisher::new(rtc.clone())
+                .with_tenant_config("a-stalled", TenantConfig { worker_count: 1, channel_capacity: 1, ..TenantConfig::default() })
+                .with_setup(Arc::new(move |tenant, publisher| match tenant {
+                    "a-stalled" => publisher.with_middleware(Arc::new(Stall(closed.clone()))),
+                    _ => publisher,
+                })));
+
+            // The worker holds the first deposit and the second fills the queue.
+            for id in 0..2 {
+                publisher.post_txn("a-stalled", Transaction::new(TransactionKind::Deposit, 1, id, Some(1.0))).await.unwrap();
+            }
+            publisher.post_txn("other", Transaction::new(TransactionKind::Deposit, 1, 0, Some(7.0))).await.unwrap();
+            // The third deposit waits for the full queue.
+            let mut stalled = Box::pin(publisher.post_txn("a-stalled", Transaction::new(TransactionKind::Deposit, 1, 2, Some(1.0))));
+            assert!(futures::poll!(stalled.as_mut()).is_pending());
+
+            // The stalled tenant can still be queried and all tenants shut down.
+            let progress = tokio::time::timeout(Duration::from_secs(5), publisher.progress("a-stalled")).await.unwrap().unwrap();
+            assert_eq!(progress.posted, 3);
+            let shutdown = publisher.clone();
+            let shutdown = rtc.spawn(async move { shutdown.shutdown_gracefully().await });
+            // The other tenant is shut down without waiting for the stalled one.
+            tokio::time::timeout(Duration::from_secs(5), publisher.shutdown_tenant("other")).await.unwrap().unwrap();
+            let other = publisher.get_report("other").await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(other[0].total, 7.0);
+
+            open.send(true).unwrap();
+            stalled.await.unwrap();
+            let results = tokio::time::timeout(Duration::from_secs(5), shutdown).await.unwrap().unwrap();
+            assert_eq!(results.len(), 2);
+            let stalled = publisher.get_report("a-stalled").await.unwrap().collect::<Vec<_>>().await;
+            assert_eq!(stalled[0].total, 3.0);
+        })
+    }
+}